@@ -13,6 +13,7 @@ use prettytable::{format, Table};
 use rand::distributions::Uniform;
 use rand::prelude::*;
 use std::io::prelude::*;
+use std::time::{Duration, Instant};
 
 use bitsets::concise::Concise;
 use bitsets::rleplus;
@@ -32,6 +33,7 @@ lazy_static! {
 fn main() {
     bench_random();
     bench_cont();
+    bench_encode_speed();
 }
 
 fn bench_random() {
@@ -212,6 +214,70 @@ fn bench_cont() {
     }
 }
 
+fn bench_encode_speed() {
+    let mut rng = rand::thread_rng();
+    let runs = 20;
+
+    for &total_sectors in &[100_000, 1_000_000, 10_000_000] {
+        for &part in &[1, 10, 50] {
+            // nothing selected
+            let mut raw = bitvec![LittleEndian; 0u8; total_sectors];
+
+            // select some, randomly
+            let selected_sectors = (total_sectors / 100) * part;
+            let sector_dist = Uniform::new(0, total_sectors);
+            for _ in 0..selected_sectors {
+                raw.set(rng.sample(sector_dist), true);
+            }
+
+            let mut encode_times = Vec::new();
+            let mut encode_fast_times = Vec::new();
+
+            for _ in 0..runs {
+                let start = Instant::now();
+                let enc = rleplus::encode(&raw);
+                encode_times.push(start.elapsed());
+
+                let start = Instant::now();
+                let enc_fast = rleplus::encode_fast(&raw);
+                encode_fast_times.push(start.elapsed());
+
+                assert_eq!(enc, enc_fast);
+            }
+
+            println!(
+                "## Encoding speed, randomly selected {}% of {}",
+                part, total_sectors
+            );
+
+            let bytes = total_sectors / 8;
+            let mut table = Table::new();
+            table.set_format(*MARKDOWN_TABLE_FORMAT);
+            table.set_titles(row!["variant", "time (in µs)", "throughput (in MB/s)"]);
+            table.add_row(
+                row!["encode", r -> average_micros(&encode_times), r -> throughput(bytes, &encode_times)],
+            );
+            table.add_row(
+                row!["encode_fast", r -> average_micros(&encode_fast_times), r -> throughput(bytes, &encode_fast_times)],
+            );
+
+            table.printstd();
+            println!("");
+        }
+    }
+}
+
+fn average_micros(times: &[Duration]) -> String {
+    let total = times.iter().sum::<Duration>();
+    format!("{:.0}", total.as_micros() as f64 / times.len() as f64)
+}
+
+fn throughput(bytes: usize, times: &[Duration]) -> String {
+    let total = times.iter().sum::<Duration>();
+    let secs = total.as_secs() as f64 + f64::from(total.subsec_nanos()) / 1e9;
+    format!("{:.2}", (bytes * times.len()) as f64 / secs / 1e6)
+}
+
 fn average(numbers: &[usize]) -> String {
     let avg = numbers.iter().sum::<usize>() as f32 / numbers.len() as f32;
     format!("{:.0}", avg)
@@ -0,0 +1,377 @@
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use bitvec::*;
+
+use super::{
+    concat, contains, count_ones, cut, decode, decoded_len, difference, encode_fast,
+    encode_indices, encode_ranges, intersect_all, intersection, into_padded_bytes, is_canonical,
+    rank, select, shift_left, shift_right, slice, slice_absolute, split_at_count, take_first,
+    union, union_all, xor, Ones, RlePlusError, RunIter,
+};
+
+/// A bitset, stored in its canonical RLE+ encoding.
+///
+/// As the canonical encoding is unique, two bitfields are equal if they hold the same bits, including any
+/// trailing `0`s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitField {
+    /// Always a valid, canonical encoding.
+    enc: BitVec<LittleEndian, u8>,
+}
+
+impl BitField {
+    /// Create an empty bitfield.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap an encoding, which must already be in its canonical form.
+    pub(crate) fn from_canonical(enc: BitVec<LittleEndian, u8>) -> Self {
+        debug_assert!(is_canonical(&enc));
+
+        BitField { enc }
+    }
+
+    /// Create a bitfield holding the given bits.
+    pub fn from_bits(raw: &BitVec<LittleEndian, u8>) -> Self {
+        BitField {
+            enc: encode_fast(raw),
+        }
+    }
+
+    /// Create a bitfield with the given set bits, see [`encode_indices`].
+    pub fn from_indices<I>(indices: I) -> Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        BitField {
+            enc: encode_indices(indices),
+        }
+    }
+
+    /// Create a bitfield with the given ranges of set bits, see [`encode_ranges`].
+    pub fn from_ranges<I>(ranges: I) -> Self
+    where
+        I: IntoIterator<Item = Range<u64>>,
+    {
+        BitField {
+            enc: encode_ranges(ranges),
+        }
+    }
+
+    /// Read a bitfield stored in bytes, as described in [Byte Layout](index.html#byte-layout), failing
+    /// if it is not in its [canonical form](index.html#canonical-form).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RlePlusError> {
+        let mut runs = RunIter::from_bytes_canonical(bytes);
+        for run in runs.by_ref() {
+            run?;
+        }
+
+        // drop the padding
+        let mut enc: BitVec<LittleEndian, u8> = bytes.to_vec().into();
+        enc.truncate(runs.position());
+
+        Ok(BitField { enc })
+    }
+
+    /// The encoding stored in bytes, as described in [Byte Layout](index.html#byte-layout).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        into_padded_bytes(self.enc.clone())
+    }
+
+    /// Decode the bitfield into its bits.
+    pub fn to_bits(&self) -> BitVec<LittleEndian, u8> {
+        decode(&self.enc)
+    }
+
+    /// The canonical RLE+ encoding of this bitfield.
+    pub fn encoding(&self) -> &BitVec<LittleEndian, u8> {
+        &self.enc
+    }
+
+    pub fn into_encoding(self) -> BitVec<LittleEndian, u8> {
+        self.enc
+    }
+
+    /// Number of bits in the bitfield, including trailing `0`s.
+    pub fn len(&self) -> u64 {
+        decoded_len(&self.enc).expect("a bitfield holds a valid encoding")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.enc.is_empty()
+    }
+
+    /// Number of set bits.
+    pub fn count(&self) -> u64 {
+        count_ones(&self.enc).expect("a bitfield holds a valid encoding")
+    }
+
+    /// Check if the bit at index `i` is set.
+    pub fn contains(&self, i: u64) -> bool {
+        contains(&self.enc, i).expect("a bitfield holds a valid encoding")
+    }
+
+    /// Index of the first set bit.
+    pub fn first(&self) -> Option<u64> {
+        self.ranges().next().map(|range| range.start)
+    }
+
+    /// Index of the last set bit.
+    pub fn last(&self) -> Option<u64> {
+        self.ranges().last().map(|range| range.end - 1)
+    }
+
+    /// Number of set bits before position `i`, see [`rank`].
+    pub fn rank(&self, i: u64) -> u64 {
+        rank(&self.enc, i).expect("a bitfield holds a valid encoding")
+    }
+
+    /// Position of the `k`-th set bit, counting from `0`, see [`select`].
+    pub fn select(&self, k: u64) -> Option<u64> {
+        select(&self.enc, k).expect("a bitfield holds a valid encoding")
+    }
+
+    /// Iterate over the indices of the set bits.
+    pub fn iter(&self) -> Ones<'_> {
+        Ones::new(&self.enc).expect("a bitfield holds a valid encoding")
+    }
+
+    /// Iterate over the ranges of set bits.
+    pub fn ranges<'a>(&'a self) -> impl Iterator<Item = Range<u64>> + 'a {
+        RunIter::new(&self.enc)
+            .ranges()
+            .map(|range| range.expect("a bitfield holds a valid encoding"))
+    }
+
+    /// Iterate over the runs of bits, as `(value, length)` pairs.
+    pub fn runs<'a>(&'a self) -> impl Iterator<Item = (bool, u64)> + 'a {
+        RunIter::new(&self.enc).map(|run| run.expect("a bitfield holds a valid encoding"))
+    }
+
+    /// The bits within `range`, moved to start at `0`, see [`slice`].
+    pub fn slice(&self, range: Range<u64>) -> Self {
+        BitField {
+            enc: slice(&self.enc, range).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// The bits within `range`, kept at their positions, see [`slice_absolute`].
+    pub fn slice_absolute(&self, range: Range<u64>) -> Self {
+        BitField {
+            enc: slice_absolute(&self.enc, range).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Drop the first `n` bits, moving all others to the front, see [`shift_left`].
+    pub fn shift_left(&self, n: u64) -> Self {
+        BitField {
+            enc: shift_left(&self.enc, n).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Insert `n` unset bits at the front, see [`shift_right`].
+    pub fn shift_right(&self, n: u64) -> Self {
+        BitField {
+            enc: shift_right(&self.enc, n).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Join this bitfield with `other`, starting at position `offset`, see [`concat`].
+    ///
+    /// Returns `None` if `offset` is smaller than the length of this bitfield, or the result would be longer
+    /// than `u64::max_value()` bits.
+    pub fn concat(&self, other: &Self, offset: u64) -> Option<Self> {
+        concat(&self.enc, &other.enc, offset)
+            .ok()
+            .map(|enc| BitField { enc })
+    }
+
+    /// Remove the positions set in `mask`, moving the remaining bits down, see [`cut`].
+    pub fn cut(&self, mask: &Self) -> Self {
+        BitField {
+            enc: cut(&self.enc, &mask.enc).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// The first `n` set bits, see [`take_first`].
+    pub fn take_first(&self, n: u64) -> Self {
+        BitField {
+            enc: take_first(&self.enc, n).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Split into the first `n` set bits and the remaining ones, see [`split_at_count`].
+    pub fn split_at_count(&self, n: u64) -> (Self, Self) {
+        let (first, rest) =
+            split_at_count(&self.enc, n).expect("a bitfield holds a valid encoding");
+
+        (BitField { enc: first }, BitField { enc: rest })
+    }
+
+    /// Union of both bitfields, see [`union`].
+    pub fn union(&self, other: &Self) -> Self {
+        BitField {
+            enc: union(&self.enc, &other.enc).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Intersection of both bitfields, see [`intersection`].
+    pub fn intersection(&self, other: &Self) -> Self {
+        BitField {
+            enc: intersection(&self.enc, &other.enc).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// The bits set in this bitfield, but not in `other`, see [`difference`].
+    pub fn difference(&self, other: &Self) -> Self {
+        BitField {
+            enc: difference(&self.enc, &other.enc).expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Union of all bitfields, merged in a single pass, see [`union_all`].
+    pub fn union_all<'a, I>(bitfields: I) -> Self
+    where
+        I: IntoIterator<Item = &'a BitField>,
+    {
+        BitField {
+            enc: union_all(bitfields.into_iter().map(|bitfield| &bitfield.enc))
+                .expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Intersection of all bitfields, merged in a single pass, see [`intersect_all`].
+    pub fn intersect_all<'a, I>(bitfields: I) -> Self
+    where
+        I: IntoIterator<Item = &'a BitField>,
+    {
+        BitField {
+            enc: intersect_all(bitfields.into_iter().map(|bitfield| &bitfield.enc))
+                .expect("a bitfield holds a valid encoding"),
+        }
+    }
+
+    /// Symmetric difference of both bitfields, see [`xor`].
+    pub fn xor(&self, other: &Self) -> Self {
+        BitField {
+            enc: xor(&self.enc, &other.enc).expect("a bitfield holds a valid encoding"),
+        }
+    }
+}
+
+impl TryFrom<BitVec<LittleEndian, u8>> for BitField {
+    type Error = RlePlusError;
+
+    /// Wrap an RLE+ encoding, failing if it is not in its [canonical form](index.html#canonical-form).
+    fn try_from(enc: BitVec<LittleEndian, u8>) -> Result<Self, Self::Error> {
+        for run in RunIter::canonical(&enc) {
+            run?;
+        }
+
+        Ok(BitField { enc })
+    }
+}
+
+impl From<BitField> for BitVec<LittleEndian, u8> {
+    fn from(bitfield: BitField) -> Self {
+        bitfield.enc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::encode;
+
+    #[test]
+    fn test_bitfield_queries() {
+        let mut rng = XorShiftRng::from_seed([8u8; 16]);
+
+        for _i in 0..1000 {
+            let len: usize = rng.gen_range(0, 20);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+            let ones: Vec<u64> = original
+                .iter()
+                .enumerate()
+                .filter(|(_, bit)| *bit)
+                .map(|(i, _)| i as u64)
+                .collect();
+
+            let bf = BitField::from_bits(&original);
+
+            assert_eq!(bf.len(), original.len() as u64);
+            assert_eq!(bf.is_empty(), original.is_empty());
+            assert_eq!(bf.count(), ones.len() as u64);
+            assert_eq!(bf.first(), ones.first().cloned());
+            assert_eq!(bf.last(), ones.last().cloned());
+            assert_eq!(bf.iter().collect::<Vec<_>>(), ones);
+            assert_eq!(bf.to_bits(), original);
+
+            for i in 0..original.len() + 8 {
+                assert_eq!(bf.contains(i as u64), original.get(i).unwrap_or(false));
+                assert_eq!(
+                    bf.rank(i as u64),
+                    ones.iter().filter(|&&one| one < i as u64).count() as u64
+                );
+                assert_eq!(bf.select(i as u64), ones.get(i).cloned());
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitfield_set_operations() {
+        let a = BitField::from_ranges(vec![0..4, 10..20]);
+        let b = BitField::from_ranges(Some(2..12));
+
+        assert_eq!(a.union(&b), BitField::from_ranges(Some(0..20)));
+        assert_eq!(
+            a.intersection(&b),
+            BitField::from_bits(
+                &bitvec![LittleEndian; 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0]
+            )
+        );
+        assert_eq!(
+            a.difference(&b).ranges().collect::<Vec<_>>(),
+            vec![0..2, 12..20]
+        );
+        assert_eq!(
+            a.xor(&b).ranges().collect::<Vec<_>>(),
+            vec![0..2, 4..10, 12..20]
+        );
+    }
+
+    #[test]
+    fn test_bitfield_conversions() {
+        let enc = encode(&bitvec![LittleEndian; 0, 0, 1, 1, 1, 0]);
+        let bf = BitField::try_from(enc.clone()).unwrap();
+        assert_eq!(bf.encoding(), &enc);
+        assert_eq!(BitVec::from(bf), enc);
+
+        // a run of 1 written as a short block
+        let enc = bitvec![LittleEndian; 0, 0, 1, 1, 0, 0, 0];
+        assert_eq!(
+            BitField::try_from(enc),
+            Err(RlePlusError::NonCanonical { offset: 1 })
+        );
+
+        let bf = BitField::from_bits(&bitvec![LittleEndian; 0, 0, 0, 0, 1, 0, 0, 0]);
+        let bytes = bf.to_bytes();
+        assert_eq!(bytes, vec![0b1010_0100, 0b0000_1110]);
+        assert_eq!(BitField::from_bytes(&bytes), Ok(bf));
+        assert_eq!(BitField::from_bytes(&[]), Ok(BitField::new()));
+        assert_eq!(
+            BitField::from_bytes(&[0b1010_0100, 0b0000_1110, 0]),
+            Err(RlePlusError::NonCanonical { offset: 14 })
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Storing a [`BitField`] as a CBOR byte string, without depending on a CBOR library.
+//!
+//! The byte string holds the encoding as described in [Byte Layout](index.html#byte-layout), preceded by
+//! a CBOR header of major type `2`, with the length written in as few bytes as possible,
+//! see [RFC 7049](https://tools.ietf.org/html/rfc7049#section-2.1).
+
+use std::{error, fmt};
+
+use super::{BitField, RlePlusError};
+
+/// Major type `2`, a byte string, in the upper 3 bits of the first byte.
+const MAJOR_BYTES: u8 = 2 << 5;
+
+/// Errors that can occur when reading a bitfield from a CBOR byte string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CborError {
+    /// The data does not start with the header of a definite length byte string.
+    InvalidHeader,
+    /// The length is not written in as few bytes as possible.
+    NonMinimalLength,
+    /// The data ended before the byte string was complete.
+    Truncated { expected: u64, available: usize },
+    /// There is data after the byte string.
+    TrailingBytes { len: usize },
+    /// The byte string does not hold a valid, canonical encoding.
+    Encoding(RlePlusError),
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CborError::InvalidHeader => write!(f, "not a definite length byte string"),
+            CborError::NonMinimalLength => write!(f, "byte string length is not minimal"),
+            CborError::Truncated {
+                expected,
+                available,
+            } => write!(
+                f,
+                "byte string of {} bytes, but only {} bytes available",
+                expected, available
+            ),
+            CborError::TrailingBytes { len } => {
+                write!(f, "{} trailing bytes after the byte string", len)
+            }
+            CborError::Encoding(err) => write!(f, "invalid encoding: {}", err),
+        }
+    }
+}
+
+impl error::Error for CborError {}
+
+impl From<RlePlusError> for CborError {
+    fn from(err: RlePlusError) -> Self {
+        CborError::Encoding(err)
+    }
+}
+
+impl BitField {
+    /// The bitfield as a CBOR byte string.
+    ///
+    /// ```
+    /// use bitsets::rleplus::BitField;
+    ///
+    /// let bf = BitField::from_indices(vec![4]);
+    /// assert_eq!(bf.to_cbor(), vec![0x41, 0b1010_0100]);
+    /// assert_eq!(BitField::from_cbor(&bf.to_cbor()), Ok(bf));
+    /// ```
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let bytes = self.to_bytes();
+
+        let mut cbor = Vec::with_capacity(bytes.len() + 9);
+        write_header(&mut cbor, bytes.len() as u64);
+        cbor.extend_from_slice(&bytes);
+
+        cbor
+    }
+
+    /// Read a bitfield from a CBOR byte string, which must make up all of `data`, failing if it does not hold
+    /// an encoding in its [canonical form](index.html#canonical-form).
+    pub fn from_cbor(data: &[u8]) -> Result<Self, CborError> {
+        let (len, header_len) = read_header(data)?;
+        let data = &data[header_len..];
+
+        if (data.len() as u64) < len {
+            return Err(CborError::Truncated {
+                expected: len,
+                available: data.len(),
+            });
+        }
+        if (data.len() as u64) > len {
+            return Err(CborError::TrailingBytes {
+                len: data.len() - len as usize,
+            });
+        }
+
+        Ok(BitField::from_bytes(data)?)
+    }
+}
+
+/// Write the header of a byte string of `len` bytes.
+fn write_header(out: &mut Vec<u8>, len: u64) {
+    if len < 24 {
+        out.push(MAJOR_BYTES | len as u8);
+    } else if len <= u64::from(u8::max_value()) {
+        out.push(MAJOR_BYTES | 24);
+        out.push(len as u8);
+    } else if len <= u64::from(u16::max_value()) {
+        out.push(MAJOR_BYTES | 25);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else if len <= u64::from(u32::max_value()) {
+        out.push(MAJOR_BYTES | 26);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(MAJOR_BYTES | 27);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Read the header of a byte string, returning the length of the byte string, and of the header.
+fn read_header(data: &[u8]) -> Result<(u64, usize), CborError> {
+    let first = *data.first().ok_or(CborError::InvalidHeader)?;
+    if first & 0xe0 != MAJOR_BYTES {
+        return Err(CborError::InvalidHeader);
+    }
+
+    let (size, min) = match first & 0x1f {
+        info @ 0..=23 => return Ok((u64::from(info), 1)),
+        24 => (1, 24),
+        25 => (2, 1 << 8),
+        26 => (4, 1 << 16),
+        27 => (8, 1 << 32),
+        // reserved, or indefinite length
+        _ => return Err(CborError::InvalidHeader),
+    };
+
+    let bytes = data.get(1..=size).ok_or(CborError::InvalidHeader)?;
+    let len = bytes
+        .iter()
+        .fold(0u64, |len, &byte| (len << 8) | u64::from(byte));
+    if len < min {
+        return Err(CborError::NonMinimalLength);
+    }
+
+    Ok((len, 1 + size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitvec::*;
+
+    #[test]
+    fn test_cbor_vectors() {
+        let cases: Vec<(BitVec<LittleEndian, u8>, Vec<u8>)> = vec![
+            (BitVec::new(), vec![0x40]),
+            (bitvec![LittleEndian; 0; 8], vec![0x41, 0b0100_0100]),
+            (
+                bitvec![LittleEndian; 0, 0, 0, 0, 1, 0, 0, 0],
+                vec![0x42, 0b1010_0100, 0b0000_1110],
+            ),
+            (bitvec![LittleEndian; 1; 16], vec![0x42, 0b1000_0001, 0]),
+        ];
+
+        for (i, (bits, cbor)) in cases.into_iter().enumerate() {
+            let bf = BitField::from_bits(&bits);
+            assert_eq!(bf.to_cbor(), cbor, "case: {}", i);
+            assert_eq!(BitField::from_cbor(&cbor), Ok(bf), "case: {}", i);
+        }
+
+        // 25 bytes of alternating bits, which need a 1 byte length
+        let bf = BitField::from_indices((0..100).map(|i| i * 2));
+        let cbor = bf.to_cbor();
+        assert_eq!(&cbor[..2], &[0x58, 25]);
+        assert_eq!(cbor.len(), 27);
+        assert_eq!(BitField::from_cbor(&cbor), Ok(bf));
+    }
+
+    #[test]
+    fn test_cbor_header() {
+        let cases: Vec<(u64, Vec<u8>)> = vec![
+            (0, vec![0x40]),
+            (23, vec![0x57]),
+            (24, vec![0x58, 24]),
+            (255, vec![0x58, 0xff]),
+            (256, vec![0x59, 0x01, 0x00]),
+            (65_536, vec![0x5a, 0x00, 0x01, 0x00, 0x00]),
+            (
+                1 << 32,
+                vec![0x5b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00],
+            ),
+        ];
+
+        for (i, (len, header)) in cases.into_iter().enumerate() {
+            let mut out = Vec::new();
+            write_header(&mut out, len);
+            assert_eq!(out, header, "case: {}", i);
+            assert_eq!(read_header(&header), Ok((len, header.len())), "case: {}", i);
+        }
+    }
+
+    #[test]
+    fn test_cbor_errors() {
+        let cases: Vec<(Vec<u8>, CborError)> = vec![
+            (vec![], CborError::InvalidHeader),
+            // a text string
+            (vec![0x61, 0x44], CborError::InvalidHeader),
+            // an indefinite length byte string
+            (vec![0x5f, 0x41, 0x44, 0xff], CborError::InvalidHeader),
+            // a missing length
+            (vec![0x59, 0x01], CborError::InvalidHeader),
+            (vec![0x58, 0x01, 0x44], CborError::NonMinimalLength),
+            (
+                vec![0x42, 0x44],
+                CborError::Truncated {
+                    expected: 2,
+                    available: 1,
+                },
+            ),
+            (vec![0x41, 0x44, 0x00], CborError::TrailingBytes { len: 1 }),
+            // a run of 1 written as a short block
+            (
+                vec![0x41, 0b0000_0100],
+                CborError::Encoding(RlePlusError::NonCanonical { offset: 1 }),
+            ),
+        ];
+
+        for (i, (cbor, err)) in cases.into_iter().enumerate() {
+            assert_eq!(BitField::from_cbor(&cbor), Err(err), "case: {}", i);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! The framed RLE+ format, storing the decoded length and a checksum in front of the encoding.
+//!
+//! A frame is laid out in bytes as
+//!
+//! ```text
+//!    <frame> ::= <version> <length> <checksum> <payload>
+//!  <version> ::= 0x02
+//!   <length> ::= <unsigned_varint>
+//! <checksum> ::= <u32, little endian>
+//!  <payload> ::= <encoding, see Byte Layout>
+//! ```
+//!
+//! where `<length>` is the number of bits of the decoded bitset, and `<checksum>` the CRC-32 (IEEE) of
+//! the payload bytes.
+
+use bitvec::*;
+
+use super::iter::{DecodeLimits, RunIter};
+use super::{decode_from_bytes, decode_runs, encode_to_bytes, RlePlusError};
+
+/// Version byte of the framed format. The plain format has no version byte and counts as version `1`.
+pub const FRAME_VERSION: u8 = 2;
+
+/// Size of the checksum in bytes.
+const CHECKSUM_SIZE: usize = 4;
+
+/// How an RLE+ encoding is stored in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Just the encoding, as described in [Byte Layout](index.html#byte-layout).
+    Plain,
+    /// The encoding, preceded by a version, the decoded length and a checksum.
+    /// See [Framed Format](index.html#framed-format).
+    Framed,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Plain
+    }
+}
+
+/// The header of a frame, which can be read without decoding the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Number of bits of the decoded bitset.
+    pub len: u64,
+    /// CRC-32 of the payload.
+    pub checksum: u32,
+    /// Size of the header in bytes, i.e. where the payload starts.
+    pub size: usize,
+}
+
+impl FrameHeader {
+    /// Read the header at the start of a frame.
+    pub fn read(bytes: &[u8]) -> Result<Self, RlePlusError> {
+        match bytes.first() {
+            None => return Err(RlePlusError::Truncated { offset: 0 }),
+            Some(&FRAME_VERSION) => {}
+            Some(_) => return Err(RlePlusError::UnsupportedVersion { offset: 0 }),
+        }
+
+        // the length starts at the second byte
+        let (len, rest) = unsigned_varint::decode::u64(&bytes[1..]).map_err(|err| match err {
+            unsigned_varint::decode::Error::Insufficient => RlePlusError::Truncated { offset: 8 },
+            _ => RlePlusError::InvalidVarint { offset: 8 },
+        })?;
+
+        let start = bytes.len() - rest.len();
+
+        // each length has a single form: the 10th byte can only hold a single bit, and the last byte
+        // is not a zero byte
+        let last = bytes[start - 1];
+        if (start == 11 && last > 1) || (start > 2 && last == 0) {
+            return Err(RlePlusError::InvalidVarint { offset: 8 });
+        }
+
+        if bytes.len() < start + CHECKSUM_SIZE {
+            return Err(RlePlusError::Truncated { offset: start * 8 });
+        }
+        let mut checksum = 0;
+        for (i, &byte) in bytes[start..start + CHECKSUM_SIZE].iter().enumerate() {
+            checksum |= u32::from(byte) << (i * 8);
+        }
+
+        Ok(FrameHeader {
+            len,
+            checksum,
+            size: start + CHECKSUM_SIZE,
+        })
+    }
+}
+
+/// Encode the given bitset into bytes, in the given format.
+pub fn encode_to_bytes_with(raw: &BitVec<LittleEndian, u8>, format: Format) -> Vec<u8> {
+    let payload = encode_to_bytes(raw);
+
+    match format {
+        Format::Plain => payload,
+        Format::Framed => {
+            let mut v = [0u8; 10];
+            let len = unsigned_varint::encode::u64(raw.len() as u64, &mut v);
+
+            let mut bytes = Vec::with_capacity(1 + len.len() + CHECKSUM_SIZE + payload.len());
+            bytes.push(FRAME_VERSION);
+            bytes.extend_from_slice(len);
+            let checksum = crc32(&payload);
+            bytes.extend((0..CHECKSUM_SIZE).map(|i| (checksum >> (i * 8)) as u8));
+            bytes.extend_from_slice(&payload);
+
+            bytes
+        }
+    }
+}
+
+/// Decode a bitset stored in bytes, in the given format, into its original form.
+///
+/// A frame is only decoded if its checksum matches, and up to the length stored in its header.
+///
+/// ```
+/// use bitsets::rleplus::{decode_from_bytes_with, encode_to_bytes_with, Format};
+/// use bitvec::*;
+///
+/// let bits = bitvec![LittleEndian, u8; 0, 0, 1, 1, 0];
+/// for &format in &[Format::Plain, Format::Framed] {
+///     let bytes = encode_to_bytes_with(&bits, format);
+///     assert_eq!(decode_from_bytes_with(&bytes, format), Ok(bits.clone()));
+/// }
+/// ```
+pub fn decode_from_bytes_with(
+    bytes: &[u8],
+    format: Format,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    match format {
+        Format::Plain => decode_from_bytes(bytes),
+        Format::Framed => {
+            let header = FrameHeader::read(bytes)?;
+            let payload = &bytes[header.size..];
+            let base = header.size * 8;
+
+            if crc32(payload) != header.checksum {
+                return Err(RlePlusError::ChecksumMismatch {
+                    offset: base - CHECKSUM_SIZE * 8,
+                });
+            }
+
+            let limits = DecodeLimits {
+                max_len: header.len,
+                ..Default::default()
+            };
+            let decoded = decode_runs(RunIter::from_bytes(payload).with_limits(limits))
+                .map_err(|err| match err {
+                    RlePlusError::LimitExceeded { offset } => {
+                        RlePlusError::LengthMismatch { offset }
+                    }
+                    err => err,
+                })
+                .map_err(|err| err.shift(base))?;
+
+            if decoded.len() as u64 != header.len {
+                return Err(RlePlusError::LengthMismatch {
+                    offset: bytes.len() * 8,
+                });
+            }
+
+            Ok(decoded)
+        }
+    }
+}
+
+/// CRC-32 as used by zlib and PNG, with the reflected polynomial `0xEDB88320`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+    }
+
+    #[test]
+    fn test_framed_layout() {
+        let cases: Vec<(BitVec<LittleEndian, u8>, Vec<u8>)> = vec![
+            (BitVec::new(), vec![2, 0, 0, 0, 0, 0]),
+            (
+                bitvec![LittleEndian, u8; 0; 8],
+                vec![2, 8, 0x04, 0x6a, 0xb3, 0xa3, 0b0100_0100],
+            ),
+        ];
+
+        for (i, (bits, bytes)) in cases.into_iter().enumerate() {
+            let encoded = encode_to_bytes_with(&bits, Format::Framed);
+            assert_eq!(encoded, bytes, "case: {}", i);
+            assert_eq!(
+                FrameHeader::read(&encoded).unwrap().len,
+                bits.len() as u64,
+                "case: {}",
+                i
+            );
+            assert_eq!(
+                decode_from_bytes_with(&encoded, Format::Framed),
+                Ok(bits),
+                "case: {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_framed_errors() {
+        let valid = encode_to_bytes_with(&bitvec![LittleEndian, u8; 0; 8], Format::Framed);
+
+        let mut bad_checksum = valid.clone();
+        bad_checksum[2] ^= 1;
+        let mut bad_payload = valid.clone();
+        bad_payload[6] ^= 0b1000_0000;
+        let mut short_len = valid.clone();
+        short_len[1] = 7;
+        let mut long_len = valid.clone();
+        long_len[1] = 9;
+
+        let cases = vec![
+            (vec![], RlePlusError::Truncated { offset: 0 }),
+            (vec![1, 0], RlePlusError::UnsupportedVersion { offset: 0 }),
+            (vec![2, 0x80], RlePlusError::Truncated { offset: 8 }),
+            (
+                vec![2, 0xff, 0xff, 0xff],
+                RlePlusError::Truncated { offset: 8 },
+            ),
+            (
+                [&[2][..], &[0xff; 10][..], &[0; 5][..]].concat(),
+                RlePlusError::InvalidVarint { offset: 8 },
+            ),
+            // 8, with high bits dropped from the 10th byte
+            (
+                [
+                    &[
+                        2, 0x88, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7e,
+                    ][..],
+                    &valid[2..],
+                ]
+                .concat(),
+                RlePlusError::InvalidVarint { offset: 8 },
+            ),
+            // 8, not written in as few bytes as possible
+            (
+                [&[2, 0x88, 0x00][..], &valid[2..]].concat(),
+                RlePlusError::InvalidVarint { offset: 8 },
+            ),
+            (vec![2, 0, 0, 0], RlePlusError::Truncated { offset: 16 }),
+            (bad_checksum, RlePlusError::ChecksumMismatch { offset: 16 }),
+            (bad_payload, RlePlusError::ChecksumMismatch { offset: 16 }),
+            (short_len, RlePlusError::LengthMismatch { offset: 49 }),
+            (long_len, RlePlusError::LengthMismatch { offset: 56 }),
+        ];
+
+        for (i, (bytes, err)) in cases.into_iter().enumerate() {
+            assert_eq!(
+                decode_from_bytes_with(&bytes, Format::Framed),
+                Err(err),
+                "case: {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_framed_roundtrip() {
+        let mut rng = XorShiftRng::from_seed([13u8; 16]);
+
+        for _i in 0..200 {
+            let len: usize = rng.gen_range(0, 1000);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let mut original: BitVec<LittleEndian, u8> = src.into();
+            // lengths which are not a multiple of 8
+            let cut = rng.gen_range(0, 8).min(original.len());
+            original.truncate(original.len() - cut);
+
+            for &format in &[Format::Plain, Format::Framed] {
+                let bytes = encode_to_bytes_with(&original, format);
+                assert_eq!(decode_from_bytes_with(&bytes, format), Ok(original.clone()));
+            }
+        }
+    }
+}
@@ -0,0 +1,544 @@
+use std::ops::Range;
+
+use bitvec::*;
+
+use super::reader::BitReader;
+use super::RlePlusError;
+
+/// Limits on the size of a decoded bitset, to guard against small encodings of huge bitsets.
+///
+/// The default imposes no limits.
+///
+/// ```
+/// use bitsets::rleplus::DecodeLimits;
+///
+/// let limits = DecodeLimits {
+///     max_len: 1 << 32,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Maximum number of bits.
+    pub max_len: u64,
+    /// Maximum number of runs, i.e. blocks.
+    pub max_runs: u64,
+    /// Maximum number of set bits.
+    pub max_ones: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_len: u64::max_value(),
+            max_runs: u64::max_value(),
+            max_ones: u64::max_value(),
+        }
+    }
+}
+
+/// Reads RLE+ blocks, keeping track of the state between them.
+#[derive(Debug, Clone)]
+pub(crate) struct BlockDecoder {
+    /// The value of the next run.
+    cur: bool,
+    /// Total length of all runs read so far.
+    total: u64,
+    /// Number of runs read so far.
+    runs: u64,
+    /// Number of set bits read so far.
+    ones: u64,
+    limits: DecodeLimits,
+    canonical: bool,
+}
+
+impl BlockDecoder {
+    /// Start decoding the blocks after a header of `cur`.
+    pub fn new(cur: bool, canonical: bool) -> Self {
+        BlockDecoder {
+            cur,
+            total: 0,
+            runs: 0,
+            ones: 0,
+            limits: DecodeLimits::default(),
+            canonical,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    /// Read the next block, returning `None` if no bits are left.
+    ///
+    /// If reading fails, the state is left unchanged.
+    pub fn read_run(
+        &mut self,
+        reader: &mut BitReader,
+    ) -> Result<Option<(bool, u64)>, RlePlusError> {
+        let offset = reader.position();
+
+        // read the next prefix
+        let run = match reader.read_bit() {
+            None => return Ok(None),
+            Some(true) => {
+                // Block Single
+                1
+            }
+            Some(false) => match reader.read_bit() {
+                Some(true) => {
+                    // Block Short
+                    // prefix: 01
+                    let run = reader
+                        .read_bits(4)
+                        .ok_or(RlePlusError::Truncated { offset })?;
+
+                    // runs of 1 are single blocks
+                    if self.canonical && run < 2 {
+                        return Err(RlePlusError::NonCanonical { offset });
+                    }
+
+                    u64::from(run)
+                }
+                Some(false) => {
+                    // Block Long
+                    // prefix: 00
+                    let run = self.read_varint(reader, offset)?;
+
+                    // runs below 16 fit into a shorter block
+                    if self.canonical && run < 16 {
+                        return Err(RlePlusError::NonCanonical { offset });
+                    }
+
+                    run
+                }
+                None => {
+                    return Err(RlePlusError::TrailingBits { offset });
+                }
+            },
+        };
+
+        let total = self
+            .total
+            .checked_add(run)
+            .ok_or(RlePlusError::LengthOverflow { offset })?;
+        let ones = if self.cur { self.ones + run } else { self.ones };
+
+        if total > self.limits.max_len
+            || self.runs + 1 > self.limits.max_runs
+            || ones > self.limits.max_ones
+        {
+            return Err(RlePlusError::LimitExceeded { offset });
+        }
+
+        let value = self.cur;
+
+        self.total = total;
+        self.runs += 1;
+        self.ones = ones;
+        // swith the cur value
+        self.cur = !self.cur;
+
+        Ok(Some((value, run)))
+    }
+
+    /// Check that the encoding can end after the blocks read so far.
+    pub fn finish(&self) -> Result<(), RlePlusError> {
+        // a header without any blocks
+        if self.canonical && self.runs == 0 {
+            return Err(RlePlusError::NonCanonical { offset: 0 });
+        }
+
+        Ok(())
+    }
+
+    /// Read an unsigned varint, as specified in https://github.com/multiformats/unsigned-varint.
+    fn read_varint(&self, reader: &mut BitReader, offset: usize) -> Result<u64, RlePlusError> {
+        let mut n: u64 = 0;
+
+        // a u64 takes at most 10 bytes
+        for i in 0..10 {
+            let byte = reader
+                .read_bits(8)
+                .ok_or(RlePlusError::Truncated { offset })?;
+
+            // the 10th byte can only hold a single bit
+            if i == 9 && byte > 1 {
+                return Err(RlePlusError::InvalidVarint { offset });
+            }
+
+            n |= u64::from(byte & 0x7f) << (i * 7);
+
+            if byte & 0x80 == 0 {
+                // a minimal varint does not end in a zero byte
+                if self.canonical && i > 0 && byte == 0 {
+                    return Err(RlePlusError::NonCanonical { offset });
+                }
+
+                return Ok(n);
+            }
+        }
+
+        Err(RlePlusError::InvalidVarint { offset })
+    }
+}
+
+/// Iterator over the runs of an RLE+ encoded bitset.
+///
+/// Yields `(value, length)` pairs, reading one block at a time, without decoding the bitset.
+/// Consecutive runs alternate in their value, starting with the value stored in the header.
+///
+/// After an error was returned, the iterator is exhausted.
+#[derive(Debug, Clone)]
+pub struct RunIter<'a> {
+    reader: BitReader<'a>,
+    decoder: BlockDecoder,
+    canonical: bool,
+    /// Position after the last set bit, if the encoding is padded to whole bytes.
+    padded_end: Option<usize>,
+    done: bool,
+}
+
+impl<'a> RunIter<'a> {
+    /// Iterate over the runs of any encoding following the RLE+ grammar.
+    pub fn new(enc: &'a BitVec<LittleEndian, u8>) -> Self {
+        Self::from_parts(enc.as_ref(), enc.len(), false)
+    }
+
+    /// Iterate over the runs of an encoding, failing if it is not in its
+    /// [canonical form](index.html#canonical-form).
+    pub fn canonical(enc: &'a BitVec<LittleEndian, u8>) -> Self {
+        Self::from_parts(enc.as_ref(), enc.len(), true)
+    }
+
+    /// Iterate over the runs of an encoding stored in bytes, see [Byte Layout](index.html#byte-layout).
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self::from_bytes_inner(bytes, false)
+    }
+
+    /// Iterate over the runs of an encoding stored in bytes, failing if it is not in its
+    /// [canonical form](index.html#canonical-form), see [Byte Layout](index.html#byte-layout).
+    pub fn from_bytes_canonical(bytes: &'a [u8]) -> Self {
+        Self::from_bytes_inner(bytes, true)
+    }
+
+    fn from_bytes_inner(bytes: &'a [u8], canonical: bool) -> Self {
+        let mut runs = Self::from_parts(bytes, bytes.len() * 8, canonical);
+        runs.padded_end = Some(padded_end(bytes));
+
+        runs
+    }
+
+    pub(crate) fn from_parts(buf: &'a [u8], len: usize, canonical: bool) -> Self {
+        let mut reader = BitReader::new(buf, len);
+
+        // Header
+        // an empty encoding has no header
+        let header = reader.read_bit();
+
+        RunIter {
+            reader,
+            decoder: BlockDecoder::new(header.unwrap_or(false), canonical),
+            canonical,
+            padded_end: None,
+            done: header.is_none(),
+        }
+    }
+
+    /// Continue iterating at the block starting at `offset`, which holds a run of `value`s starting at
+    /// position `pos` of the decoded bitset.
+    pub(crate) fn resume(
+        enc: &'a BitVec<LittleEndian, u8>,
+        offset: usize,
+        value: bool,
+        pos: u64,
+    ) -> Self {
+        let mut runs = Self::new(enc);
+        runs.reader.seek(offset);
+        runs.decoder.cur = value;
+        runs.decoder.total = pos;
+
+        runs
+    }
+
+    /// Fail with [`RlePlusError::LimitExceeded`] as soon as the runs read exceed the given limits.
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.decoder.set_limits(limits);
+        self
+    }
+
+    /// The limits set with [`RunIter::with_limits`].
+    pub(crate) fn limits(&self) -> DecodeLimits {
+        self.decoder.limits
+    }
+
+    /// Bit position in the encoding of the next block to read.
+    pub fn position(&self) -> usize {
+        self.reader.position()
+    }
+
+    /// Iterate over the ranges of set bits, instead of the runs.
+    pub fn ranges(self) -> Ranges<'a> {
+        Ranges { runs: self, pos: 0 }
+    }
+
+    fn read_run(&mut self) -> Result<Option<(bool, u64)>, RlePlusError> {
+        let offset = self.reader.position();
+
+        // only zeros are left, which can't be a valid block
+        if self.padded_end.map_or(false, |end| offset >= end) {
+            // the padding must end with the first byte that is not fully used
+            if self.canonical && self.reader.remaining() >= 8 {
+                return Err(RlePlusError::NonCanonical { offset });
+            }
+        } else if let Some(run) = self.decoder.read_run(&mut self.reader)? {
+            return Ok(Some(run));
+        }
+
+        self.decoder.finish()?;
+
+        Ok(None)
+    }
+}
+
+/// Position after the last set bit in `bytes`, everything after it could be padding.
+pub(crate) fn padded_end(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |i| i * 8 + 8 - bytes[i].leading_zeros() as usize)
+}
+
+impl<'a> Iterator for RunIter<'a> {
+    type Item = Result<(bool, u64), RlePlusError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_run() {
+            Ok(Some(run)) => Some(Ok(run)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Iterator over the ranges of set bits of an RLE+ encoded bitset, created by [`RunIter::ranges`].
+#[derive(Debug, Clone)]
+pub struct Ranges<'a> {
+    runs: RunIter<'a>,
+    /// Start of the next run.
+    pos: u64,
+}
+
+impl<'a> Iterator for Ranges<'a> {
+    type Item = Result<Range<u64>, RlePlusError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut range: Option<Range<u64>> = None;
+
+        loop {
+            let (value, len) = match self.runs.next() {
+                Some(Ok(run)) => run,
+                Some(Err(err)) => return Some(Err(err)),
+                None => return range.map(Ok),
+            };
+
+            let start = self.pos;
+            // the run iterator checks the total length for overflows
+            self.pos += len;
+
+            // zero length runs, only found in non-canonical encodings, can join two ranges
+            if len == 0 {
+                continue;
+            }
+
+            if value {
+                match range {
+                    Some(ref mut range) => range.end = self.pos,
+                    None => range = Some(start..self.pos),
+                }
+            } else if range.is_some() {
+                return range.map(Ok);
+            }
+        }
+    }
+}
+
+/// Iterator over the indices of the set bits of an RLE+ encoded bitset.
+///
+/// The encoding is validated once on creation, which also counts the set bits, so that iterating is
+/// infallible and the exact number of remaining indices is known.
+#[derive(Debug, Clone)]
+pub struct Ones<'a> {
+    ranges: Ranges<'a>,
+    /// The set bits of the current range, not yet yielded.
+    current: Range<u64>,
+    /// Number of set bits not yet yielded.
+    remaining: u64,
+}
+
+impl<'a> Ones<'a> {
+    /// Create an iterator over the set bits, failing if the encoding is malformed.
+    pub fn new(enc: &'a BitVec<LittleEndian, u8>) -> Result<Self, RlePlusError> {
+        let mut remaining = 0;
+        for run in RunIter::new(enc) {
+            let (value, len) = run?;
+            if value {
+                remaining += len;
+            }
+        }
+
+        Ok(Ones {
+            ranges: RunIter::new(enc).ranges(),
+            current: 0..0,
+            remaining,
+        })
+    }
+}
+
+impl<'a> Iterator for Ones<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.current.start == self.current.end {
+            // skip directly to the next range of set bits
+            self.current = self
+                .ranges
+                .next()?
+                .expect("the encoding was validated on creation");
+        }
+
+        self.remaining -= 1;
+        self.current.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.remaining > usize::max_value() as u64 {
+            (usize::max_value(), None)
+        } else {
+            (self.remaining as usize, Some(self.remaining as usize))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::encode;
+
+    #[test]
+    fn test_run_iter_basics() {
+        let enc = encode(&bitvec![LittleEndian; 0, 0, 0, 0, 1, 0, 0, 0]);
+        let runs = RunIter::new(&enc).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(runs, vec![(false, 4), (true, 1), (false, 3)]);
+
+        let enc = encode(&bitvec![LittleEndian; 1; 20]);
+        let runs = RunIter::new(&enc).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(runs, vec![(true, 20)]);
+
+        assert_eq!(RunIter::new(&BitVec::new()).next(), None);
+
+        // errors end the iteration
+        let enc = bitvec![LittleEndian; 0, 1, 0];
+        let mut runs = RunIter::new(&enc);
+        assert_eq!(runs.next(), Some(Ok((false, 1))));
+        assert_eq!(
+            runs.next(),
+            Some(Err(RlePlusError::TrailingBits { offset: 2 }))
+        );
+        assert_eq!(runs.next(), None);
+    }
+
+    #[test]
+    fn test_run_iter_ranges() {
+        let mut rng = XorShiftRng::from_seed([4u8; 16]);
+
+        for _i in 0..1000 {
+            let len: usize = rng.gen_range(0, 100);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+            let encoded = encode(&original);
+
+            let mut expected: Vec<Range<u64>> = Vec::new();
+            for (i, bit) in original.iter().enumerate() {
+                let i = i as u64;
+                if !bit {
+                    continue;
+                }
+                match expected.last_mut() {
+                    Some(ref mut range) if range.end == i => range.end += 1,
+                    _ => expected.push(i..i + 1),
+                }
+            }
+
+            let ranges = RunIter::new(&encoded)
+                .ranges()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(ranges, expected);
+        }
+    }
+
+    #[test]
+    fn test_ones() {
+        let mut rng = XorShiftRng::from_seed([5u8; 16]);
+
+        for _i in 0..1000 {
+            let len: usize = rng.gen_range(0, 100);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+            let encoded = encode(&original);
+
+            let expected: Vec<u64> = original
+                .iter()
+                .enumerate()
+                .filter(|(_, bit)| *bit)
+                .map(|(i, _)| i as u64)
+                .collect();
+
+            let ones = Ones::new(&encoded).unwrap();
+            assert_eq!(ones.size_hint(), (expected.len(), Some(expected.len())));
+            assert_eq!(ones.collect::<Vec<_>>(), expected);
+        }
+
+        assert_eq!(
+            Ones::new(&bitvec![LittleEndian; 0, 1, 0]).unwrap_err(),
+            RlePlusError::TrailingBits { offset: 2 }
+        );
+    }
+
+    #[test]
+    fn test_run_iter_ranges_non_canonical() {
+        // 1 1 | 0 (long block of length 0) | 1
+        let enc = bitvec![LittleEndian;
+                          1,
+                          0, 1, 0, 1, 0, 0,
+                          0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                          1
+        ];
+        let ranges = RunIter::new(&enc)
+            .ranges()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(ranges, vec![0..3]);
+    }
+}
@@ -0,0 +1,1035 @@
+//! # RLE+ Bitset Encoding
+//!
+//! RLE+ is a lossless compression format based on [RLE](https://en.wikipedia.org/wiki/Run-length_encoding).
+//! It's primary goal is to reduce the size in the case of many individual bits, where RLE breaks down quickly,
+//! while keeping the same level of compression for large sets of contigous bits.
+//!
+//! In tests it has shown to be more compact than RLE iteself, as well as [Concise](https://arxiv.org/pdf/1004.0403.pdf) and [Roaring](https://roaringbitmap.org/).
+//!
+//! ## Format
+//!
+//! The format consists of a header, followed by a series of blocks, of which there are three different types.
+//!
+//! The format can be expressed as the following [BNF](https://en.wikipedia.org/wiki/Backus%E2%80%93Naur_form) grammar.
+//!
+//! ```text
+//!     <encoding> ::= <header> <blocks>
+//!       <header> ::= <bit>
+//!       <blocks> ::= <block_single> | <block_short> | <block_long>
+//! <block_single> ::= "1"
+//!  <block_short> ::= "01" <bit> <bit> <bit> <bit>
+//!   <block_long> ::= "00" <unsigned_varint>
+//!          <bit> ::= "0" | "1"
+//! ```
+//!
+//! An `<unsigned_varint>` is defined as specified [here](https://github.com/multiformats/unsigned-varint).
+//!
+//! ### Header
+//!
+//! The header indiciates the very first bit of the bit vector to encode. This means the first bit is always
+//! the same for the encoded and non encoded form.
+//!
+//! ### Blocks
+//!
+//! The blocks represent how many bits, of the current bit type there are. As `0` and `1` alternate in a bit vector
+//! the inital bit, which is stored in the header, is enough to determine if a length is currently referencing
+//! a set of `0`s, or `1`s.
+//!
+//! #### Block Single
+//!
+//! If the running length of the current bit is only `1`, it is encoded as a single set bit.
+//!
+//! #### Block Short
+//!
+//! If the running length is less than `16`, it can be encoded into up to four bits, which a short block
+//! represents. The length is encoded into a 4 bits, and prefixed with `01`, to indicate a short block.
+//!
+//! #### Block Long
+//!
+//! If the running length is `16` or larger, it is encoded into a varint, and then prefixed with `00` to indicate
+//! a long block.
+//!
+//!
+//! > **Note:** The encoding is unique, so no matter which algorithm for encoding is used, it should produce
+//! > the same encoding, given the same input.
+//!
+//! ### Canonical Form
+//!
+//! The grammar above allows more than one encoding of the same bitset, e.g. a run of `3` can also be written
+//! as a long block. The canonical encoding, as produced by [`encode`], additionally requires that
+//!
+//! - every run is at least `1` bit long,
+//! - a run of `1` is written as a single block,
+//! - a run of `2` to `15` is written as a short block,
+//! - a run of `16` or more is written as a long block, holding a minimal varint,
+//! - an empty bitset is written as an empty encoding, without a header.
+//!
+//! [`try_decode`] accepts any encoding following the grammar, while [`try_decode_canonical`] and
+//! [`is_canonical`] only accept the canonical one. The latter rejects zero length runs in particular,
+//! which is what zero padding at the end of an encoding would be decoded as.
+//!
+//! ### Byte Layout
+//!
+//! When stored in bytes, the bits of the encoding are packed least significant bit first, so the header
+//! is the lowest bit of the first byte. This is the same layout as that of a `BitVec<LittleEndian, u8>`.
+//!
+//! The last byte is padded with `0`s. As no block consists of only `0`s and fits into less than `8` bits,
+//! [`decode_from_bytes`] stops as soon as only `0`s are left at the start of a block, and ignores them.
+//! [`decode_from_bytes_canonical`] additionally requires these to be fewer than `8`, i.e. no bytes
+//! beyond the one holding the last block.
+//!
+//! ### Framed Format
+//!
+//! The header only holds the first bit, so neither the decoded length nor corruption of the encoding
+//! can be detected before decoding. The framed format, selected with [`Format::Framed`], stores the
+//! encoding in its byte layout after a version byte, the decoded length as a varint and a CRC-32 of
+//! the encoding. Use [`encode_to_bytes_with`] and [`decode_from_bytes_with`] to work with either format,
+//! and [`FrameHeader::read`] to get the length before decoding. The plain format remains the default.
+//!
+//! ### Text Format
+//!
+//! For debugging, a [`BitField`] is displayed as the list of its ranges of set bits, and can be parsed back.
+//!
+//! ```text
+//! <bitfield> ::= <ranges> | <ranges> "/" <index>
+//!   <ranges> ::= "" | <range> | <range> "," <ranges>
+//!    <range> ::= <index> | <index> "-" <index>
+//!    <index> ::= <digit> | <digit> <index>
+//! ```
+//!
+//! Ranges include both ends, must be sorted and must not overlap, e.g. `"1,3-7,100-120"`. A bitfield with
+//! trailing `0`s, after its last set bit, additionally gets its length after a `/`, e.g. `"1,3-7/10"`.
+//!
+
+mod bitfield;
+mod cbor;
+mod framed;
+mod iter;
+mod ops;
+mod rank;
+mod reader;
+#[cfg(feature = "serde")]
+mod serialize;
+mod stream;
+mod text;
+mod transform;
+mod words;
+mod writer;
+
+pub use self::bitfield::BitField;
+pub use self::cbor::CborError;
+pub use self::framed::{
+    decode_from_bytes_with, encode_to_bytes_with, Format, FrameHeader, FRAME_VERSION,
+};
+pub use self::iter::{DecodeLimits, Ones, Ranges, RunIter};
+pub use self::ops::{difference, intersect_all, intersection, union, union_all, xor};
+pub use self::rank::{contains, contains_many, rank, select, SkipIndex};
+pub use self::stream::{RlePlusReader, RlePlusWriter};
+pub use self::text::ParseBitFieldError;
+pub use self::transform::{
+    concat, cut, shift_left, shift_right, slice, slice_absolute, split_at_count, take_first,
+};
+
+use std::ops::Range;
+use std::{error, fmt, io};
+
+use bitvec::*;
+
+use self::words::WordRuns;
+use self::writer::{fill_bits, RunEncoder};
+
+/// Encode the given bitset into their RLE+ encoded representation.
+///
+/// This looks at every bit on its own, [`encode_fast`] produces the same encoding a lot faster.
+pub fn encode(raw: &BitVec<LittleEndian, u8>) -> BitVec<LittleEndian, u8> {
+    let mut encoding = BitVec::new();
+
+    if raw.is_empty() {
+        return encoding;
+    }
+
+    // Header
+    // encode the very first bit (the first block contains this, then alternating)
+    encoding.push(raw.get(0).unwrap());
+
+    // the running length
+    let mut count = 1;
+
+    // the current bit type
+    let mut current = raw.get(0);
+
+    let last = raw.len();
+
+    for i in 1..=raw.len() {
+        if raw.get(i) != current || i == last {
+            if i == last && raw.get(i) == current {
+                count += 1;
+            }
+
+            if count == 1 {
+                // Block Single
+                encoding.push(true);
+            } else if count < 16 {
+                // Block Short
+                // 4 bits
+                let s_vec: BitVec<LittleEndian, u8> = BitVec::from(&[count as u8][..]);
+
+                // prefix: 01
+                encoding.push(false);
+                encoding.push(true);
+                encoding.extend(s_vec.iter().take(4));
+                count = 1;
+            } else {
+                // Block Long
+                let mut v = [0u8; 10];
+                let s = unsigned_varint::encode::u64(count, &mut v);
+                let s_vec: BitVec<LittleEndian, u8> = BitVec::from(s);
+
+                // prefix: 00
+                encoding.push(false);
+                encoding.push(false);
+
+                encoding.extend(s_vec.iter());
+                count = 1;
+            }
+            current = raw.get(i);
+        } else {
+            count += 1;
+        }
+    }
+
+    encoding
+}
+
+/// Encode the given bitset into their RLE+ encoded representation, the same as [`encode`].
+///
+/// Finds the runs by scanning the bitset 64 bits at a time, and writes the blocks directly into bytes.
+pub fn encode_fast(raw: &BitVec<LittleEndian, u8>) -> BitVec<LittleEndian, u8> {
+    // most encodings are smaller than the bitset itself
+    let mut encoder = RunEncoder::with_capacity((raw.len() + 7) / 8);
+    for (value, len) in WordRuns::new(raw) {
+        encoder.push_run(value, len);
+    }
+
+    encoder.finish()
+}
+
+/// Encode the bitset with the given set bits into its RLE+ encoded representation.
+///
+/// The result is the same as encoding the dense bitset, which ends with the last set bit, using [`encode`].
+///
+/// # Panics
+///
+/// Panics if the indices are not strictly increasing.
+pub fn encode_indices<I>(indices: I) -> BitVec<LittleEndian, u8>
+where
+    I: IntoIterator<Item = u64>,
+{
+    encode_ranges(indices.into_iter().map(|i| {
+        assert!(i < u64::max_value(), "index out of bounds");
+        i..i + 1
+    }))
+}
+
+/// Encode the bitset with the given ranges of set bits into its RLE+ encoded representation.
+///
+/// The ranges may touch, but must not overlap, and empty ranges are ignored.
+/// The result is the same as encoding the dense bitset, which ends with the last set bit, using [`encode`].
+///
+/// # Panics
+///
+/// Panics if the ranges are not sorted, or overlap.
+pub fn encode_ranges<I>(ranges: I) -> BitVec<LittleEndian, u8>
+where
+    I: IntoIterator<Item = Range<u64>>,
+{
+    let mut encoder = RunEncoder::new();
+
+    // end of the last range
+    let mut pos = 0;
+
+    for range in ranges {
+        if range.start >= range.end {
+            continue;
+        }
+        assert!(range.start >= pos, "ranges must be sorted and not overlap");
+
+        encoder.push_run(false, range.start - pos);
+        encoder.push_run(true, range.end - range.start);
+        pos = range.end;
+    }
+
+    encoder.finish()
+}
+
+/// Errors that can occur when decoding an RLE+ encoded bitset.
+///
+/// All offsets are bit positions into the encoding, pointing at the start of the offending block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RlePlusError {
+    /// The encoding ended before the block was complete.
+    Truncated { offset: usize },
+    /// A long block does not hold a valid varint.
+    InvalidVarint { offset: usize },
+    /// The run length of a block does not fit into the decoded bitset.
+    LengthOverflow { offset: usize },
+    /// A dangling bit was left after the last block, which is too short to start a new block.
+    TrailingBits { offset: usize },
+    /// The block is valid, but not in its canonical form.
+    NonCanonical { offset: usize },
+    /// The decoded bitset would exceed the configured [`DecodeLimits`].
+    LimitExceeded { offset: usize },
+    /// The frame has a version other than [`FRAME_VERSION`].
+    UnsupportedVersion { offset: usize },
+    /// The payload of the frame does not match its checksum.
+    ChecksumMismatch { offset: usize },
+    /// The payload of the frame does not decode to the length stored in its header.
+    LengthMismatch { offset: usize },
+    /// The bitsets to join overlap, the block of the first one reaches past the start of the second one.
+    Overlap { offset: usize },
+}
+
+impl RlePlusError {
+    /// Move the offset by `by` bits, for an encoding embedded at that position.
+    pub(crate) fn shift(mut self, by: usize) -> Self {
+        match self {
+            RlePlusError::Truncated { ref mut offset }
+            | RlePlusError::InvalidVarint { ref mut offset }
+            | RlePlusError::LengthOverflow { ref mut offset }
+            | RlePlusError::TrailingBits { ref mut offset }
+            | RlePlusError::NonCanonical { ref mut offset }
+            | RlePlusError::LimitExceeded { ref mut offset }
+            | RlePlusError::UnsupportedVersion { ref mut offset }
+            | RlePlusError::ChecksumMismatch { ref mut offset }
+            | RlePlusError::LengthMismatch { ref mut offset }
+            | RlePlusError::Overlap { ref mut offset } => *offset += by,
+        }
+
+        self
+    }
+}
+
+impl fmt::Display for RlePlusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RlePlusError::Truncated { offset } => {
+                write!(f, "truncated block at bit {}", offset)
+            }
+            RlePlusError::InvalidVarint { offset } => {
+                write!(f, "invalid varint in long block at bit {}", offset)
+            }
+            RlePlusError::LengthOverflow { offset } => {
+                write!(f, "run length overflow in block at bit {}", offset)
+            }
+            RlePlusError::TrailingBits { offset } => {
+                write!(f, "trailing bits at bit {}", offset)
+            }
+            RlePlusError::NonCanonical { offset } => {
+                write!(f, "non-canonical block at bit {}", offset)
+            }
+            RlePlusError::LimitExceeded { offset } => {
+                write!(f, "decode limit exceeded in block at bit {}", offset)
+            }
+            RlePlusError::UnsupportedVersion { offset } => {
+                write!(f, "unsupported frame version at bit {}", offset)
+            }
+            RlePlusError::ChecksumMismatch { offset } => {
+                write!(f, "checksum mismatch at bit {}", offset)
+            }
+            RlePlusError::LengthMismatch { offset } => {
+                write!(
+                    f,
+                    "decoded length does not match the frame at bit {}",
+                    offset
+                )
+            }
+            RlePlusError::Overlap { offset } => {
+                write!(f, "bitsets overlap in block at bit {}", offset)
+            }
+        }
+    }
+}
+
+impl error::Error for RlePlusError {}
+
+impl From<RlePlusError> for io::Error {
+    fn from(err: RlePlusError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Decode an RLE+ encoded bitset into its original form.
+///
+/// # Panics
+///
+/// Panics if the encoding is malformed, use [`try_decode`] to handle untrusted input.
+pub fn decode(enc: &BitVec<LittleEndian, u8>) -> BitVec<LittleEndian, u8> {
+    match try_decode(enc) {
+        Ok(decoded) => decoded,
+        Err(err) => panic!("invalid RLE+ encoding: {}", err),
+    }
+}
+
+/// Decode an RLE+ encoded bitset into its original form, returning an error if the encoding is malformed.
+///
+/// A few bytes of encoding can describe a bitset too large to fit into memory, which fails with
+/// [`RlePlusError::LengthOverflow`]. Use [`decode_runs`] with [`DecodeLimits`], or
+/// [`decode_from_bytes_with_limits`], to bound the size of untrusted input.
+pub fn try_decode(
+    enc: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    decode_runs(RunIter::new(enc))
+}
+
+/// Decode an RLE+ encoded bitset into its original form, returning an error if the encoding is malformed
+/// or not in its [canonical form](index.html#canonical-form).
+pub fn try_decode_canonical(
+    enc: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    decode_runs(RunIter::canonical(enc))
+}
+
+/// Check if the given encoding is a valid RLE+ encoding in its [canonical form](index.html#canonical-form).
+pub fn is_canonical(enc: &BitVec<LittleEndian, u8>) -> bool {
+    RunIter::canonical(enc).all(|run| run.is_ok())
+}
+
+/// Encode the given bitset into its RLE+ encoded representation, stored in bytes as described in
+/// [Byte Layout](index.html#byte-layout).
+pub fn encode_to_bytes(raw: &BitVec<LittleEndian, u8>) -> Vec<u8> {
+    into_padded_bytes(encode_fast(raw))
+}
+
+/// Store an encoding in bytes, as described in [Byte Layout](index.html#byte-layout).
+pub(crate) fn into_padded_bytes(enc: BitVec<LittleEndian, u8>) -> Vec<u8> {
+    let len = enc.len();
+
+    let mut bytes: Vec<u8> = enc.into();
+
+    // make sure the padding is zeroed
+    if len % 8 != 0 {
+        *bytes.last_mut().unwrap() &= (1 << (len % 8)) - 1;
+    }
+
+    bytes
+}
+
+/// Decode an RLE+ encoded bitset stored in bytes, as described in [Byte Layout](index.html#byte-layout),
+/// into its original form.
+///
+/// Fails with [`RlePlusError::LengthOverflow`] if the bitset does not fit into memory, use
+/// [`decode_from_bytes_with_limits`] to decode untrusted input.
+pub fn decode_from_bytes(bytes: &[u8]) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    decode_runs(RunIter::from_bytes(bytes))
+}
+
+/// Decode an RLE+ encoded bitset stored in bytes, as described in [Byte Layout](index.html#byte-layout),
+/// into its original form, failing with [`RlePlusError::LimitExceeded`] as soon as it exceeds `limits`.
+///
+/// ```
+/// use bitsets::rleplus::{decode_from_bytes_with_limits, DecodeLimits, RlePlusError};
+///
+/// let limits = DecodeLimits {
+///     max_len: 4,
+///     ..Default::default()
+/// };
+/// assert_eq!(
+///     decode_from_bytes_with_limits(&[0b0100_0100], limits),
+///     Err(RlePlusError::LimitExceeded { offset: 1 })
+/// );
+/// ```
+pub fn decode_from_bytes_with_limits(
+    bytes: &[u8],
+    limits: DecodeLimits,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    decode_runs(RunIter::from_bytes(bytes).with_limits(limits))
+}
+
+/// Decode an RLE+ encoded bitset stored in bytes, as described in [Byte Layout](index.html#byte-layout),
+/// into its original form, failing if it is not in its [canonical form](index.html#canonical-form).
+pub fn decode_from_bytes_canonical(bytes: &[u8]) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    decode_runs(RunIter::from_bytes_canonical(bytes))
+}
+
+/// Number of set bits in an RLE+ encoded bitset, computed from the blocks without decoding.
+pub fn count_ones(enc: &BitVec<LittleEndian, u8>) -> Result<u64, RlePlusError> {
+    count_runs(enc, |value| value)
+}
+
+/// Number of unset bits in an RLE+ encoded bitset, computed from the blocks without decoding.
+pub fn count_zeros(enc: &BitVec<LittleEndian, u8>) -> Result<u64, RlePlusError> {
+    count_runs(enc, |value| !value)
+}
+
+/// Number of bits in an RLE+ encoded bitset, computed from the blocks without decoding.
+pub fn decoded_len(enc: &BitVec<LittleEndian, u8>) -> Result<u64, RlePlusError> {
+    count_runs(enc, |_| true)
+}
+
+/// Total length of the runs whose value matches `filter`.
+fn count_runs<F>(enc: &BitVec<LittleEndian, u8>, filter: F) -> Result<u64, RlePlusError>
+where
+    F: Fn(bool) -> bool,
+{
+    let mut count = 0;
+    for run in RunIter::new(enc) {
+        let (value, len) = run?;
+        if filter(value) {
+            // the run iterator checks the total length for overflows
+            count += len;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Decode the bitset from its runs.
+///
+/// This allows decoding with [`DecodeLimits`] in place, e.g.
+///
+/// ```
+/// use bitsets::rleplus::{decode_runs, DecodeLimits, RunIter};
+///
+/// let limits = DecodeLimits {
+///     max_len: 1 << 20,
+///     ..Default::default()
+/// };
+/// let bits = decode_runs(RunIter::from_bytes(&[0b0100_0100]).with_limits(limits)).unwrap();
+/// assert_eq!(bits.len(), 8);
+/// ```
+pub fn decode_runs(mut runs: RunIter) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let mut bytes = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        // start of the current block, for error reporting
+        let offset = runs.position();
+
+        let (value, len) = match runs.next() {
+            Some(run) => run?,
+            None => break,
+        };
+
+        if len > (usize::max_value() - 7 - pos) as u64 {
+            return Err(RlePlusError::LengthOverflow { offset });
+        }
+        let len = len as usize;
+
+        // a few bytes of encoding can describe more bits than fit into memory
+        let new_len = (pos + len + 7) / 8;
+        bytes
+            .try_reserve(new_len - bytes.len())
+            .map_err(|_| RlePlusError::LengthOverflow { offset })?;
+
+        // the new bytes are unset already
+        bytes.resize(new_len, 0);
+        if value {
+            fill_bits(&mut bytes, pos, len, true);
+        }
+        pos += len;
+    }
+
+    let mut decoded: BitVec<LittleEndian, u8> = bytes.into();
+    decoded.truncate(pos);
+
+    Ok(decoded)
+}
+
+/// Decode the bitset from its runs into `buf`, without allocating, and return the number of bits decoded.
+///
+/// Bits of `buf` beyond the decoded ones are left as they are. Fails with [`RlePlusError::LimitExceeded`]
+/// if the bitset does not fit into `buf`, or exceeds the limits already set on `runs`.
+///
+/// ```
+/// use bitsets::rleplus::{decode_into, RunIter};
+///
+/// let mut buf = [0u8; 4];
+/// let len = decode_into(RunIter::from_bytes(&[0b1010_0100, 0b0000_1110]), &mut buf).unwrap();
+/// assert_eq!(len, 8);
+/// assert_eq!(buf[0], 0b0001_0000);
+/// ```
+pub fn decode_into(runs: RunIter, buf: &mut [u8]) -> Result<usize, RlePlusError> {
+    // also makes sure the position does not overflow
+    let limits = runs.limits();
+    let limits = DecodeLimits {
+        max_len: limits.max_len.min((buf.len() as u64).saturating_mul(8)),
+        ..limits
+    };
+
+    let mut pos = 0;
+    for run in runs.with_limits(limits) {
+        let (value, len) = run?;
+
+        fill_bits(buf, pos, len as usize, value);
+        pos += len as usize;
+    }
+
+    Ok(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    /// A random bitset of less than `max_bytes` bytes, with long runs of both values.
+    pub(crate) fn random_bitvec(
+        rng: &mut XorShiftRng,
+        max_bytes: usize,
+    ) -> BitVec<LittleEndian, u8> {
+        let len: usize = rng.gen_range(0, max_bytes);
+
+        let mut src = vec![0u8; len];
+        rng.fill_bytes(&mut src);
+
+        // make long runs more likely
+        for byte in src.iter_mut() {
+            match rng.gen_range(0, 3) {
+                0 => *byte = 0,
+                1 => *byte = 0xff,
+                _ => {}
+            }
+        }
+
+        src.into()
+    }
+
+    #[test]
+    fn test_rle_plus_basics() {
+        let cases = vec![
+            (
+                bitvec![LittleEndian; 0; 8],
+                bitvec![LittleEndian;
+                        0, // starts with 0
+                        0, 1, // fits into 4 bits
+                        0, 0, 0, 1, // 8
+                ],
+            ),
+            (
+                bitvec![LittleEndian; 0, 0, 0, 0, 1, 0, 0, 0],
+                bitvec![LittleEndian;
+                        0, // starts with 0
+                        0, 1, // fits into 4 bits
+                        0, 0, 1, 0, // 4 - 0
+                        1, // 1 - 1
+                        0, 1, // fits into 4 bits
+                        1, 1, 0, 0 // 3 - 0
+                ],
+            ),
+        ];
+
+        for (i, case) in cases.into_iter().enumerate() {
+            assert_eq!(encode(&case.0), case.1, "case: {}", i);
+        }
+    }
+
+    #[test]
+    fn test_rle_plus_decode_errors() {
+        let cases = vec![
+            (
+                // dangling prefix bit
+                bitvec![LittleEndian; 0, 1, 0],
+                RlePlusError::TrailingBits { offset: 2 },
+            ),
+            (
+                // short block missing its last bit
+                bitvec![LittleEndian; 0, 0, 1, 0, 0, 0],
+                RlePlusError::Truncated { offset: 1 },
+            ),
+            (
+                // long block with an unterminated varint
+                bitvec![LittleEndian; 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1],
+                RlePlusError::Truncated { offset: 1 },
+            ),
+            (
+                // long block with a varint missing its last bits
+                bitvec![LittleEndian; 0, 0, 0, 0, 0, 0, 0, 1],
+                RlePlusError::Truncated { offset: 1 },
+            ),
+        ];
+
+        for (i, case) in cases.into_iter().enumerate() {
+            assert_eq!(try_decode(&case.0), Err(case.1), "case: {}", i);
+        }
+
+        // long block with a varint of more than 10 bytes
+        let mut enc = bitvec![LittleEndian; 0, 0, 0];
+        enc.extend(BitVec::<LittleEndian, u8>::from(&[0xff; 11][..]).iter());
+        assert_eq!(
+            try_decode(&enc),
+            Err(RlePlusError::InvalidVarint { offset: 1 })
+        );
+    }
+
+    #[test]
+    fn test_rle_plus_non_canonical() {
+        let cases = vec![
+            // header only
+            bitvec![LittleEndian; 1],
+            // short block holding a run of 1
+            bitvec![LittleEndian; 0, 0, 1, 1, 0, 0, 0],
+            // short block holding a run of 0
+            bitvec![LittleEndian; 0, 1, 0, 1, 0, 0, 0, 0],
+            // long block holding a run of 15
+            bitvec![LittleEndian;
+                    0,
+                    0, 0, 1, 1, 1, 1, 0, 0, 0, 0
+            ],
+            // long block holding a run of 0, as left by zero padding
+            bitvec![LittleEndian;
+                    0,
+                    1,
+                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+            ],
+            // long block holding a non minimal varint of 16
+            bitvec![LittleEndian;
+                    0,
+                    0, 0, 0, 0, 0, 0, 1, 0, 0, 1,
+                    0, 0, 0, 0, 0, 0, 0, 0
+            ],
+        ];
+
+        for (i, case) in cases.into_iter().enumerate() {
+            assert!(try_decode(&case).is_ok(), "case: {}", i);
+            assert!(!is_canonical(&case), "case: {}", i);
+        }
+
+        assert!(is_canonical(&BitVec::new()));
+        assert!(is_canonical(&bitvec![LittleEndian;
+                                      0,
+                                      0, 0, 0, 0, 0, 0, 1, 0, 0, 0
+        ]));
+    }
+
+    #[test]
+    fn test_rle_plus_decode_truncated() {
+        let mut rng = XorShiftRng::from_seed([3u8; 16]);
+
+        for _i in 0..1000 {
+            let len: usize = rng.gen_range(1, 100);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let encoded = encode(&src.into());
+            let cut = rng.gen_range(0, encoded.len());
+            let truncated = encoded.iter().take(cut).collect();
+
+            // must never panic
+            let _ = try_decode(&truncated);
+        }
+    }
+
+    #[test]
+    fn test_rle_plus_encode_indices() {
+        let mut rng = XorShiftRng::from_seed([6u8; 16]);
+
+        for _i in 0..1000 {
+            let len: usize = rng.gen_range(0, 100);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let mut original: BitVec<LittleEndian, u8> = src.into();
+            // the dense form ends with the last set bit
+            while !original.is_empty() && !original.get(original.len() - 1).unwrap() {
+                original.pop();
+            }
+
+            let indices = original
+                .iter()
+                .enumerate()
+                .filter(|(_, bit)| *bit)
+                .map(|(i, _)| i as u64);
+
+            let ranges = RunIter::new(&encode(&original))
+                .ranges()
+                .map(|range| range.unwrap())
+                .collect::<Vec<_>>();
+
+            assert_eq!(encode_indices(indices), encode(&original));
+            assert_eq!(encode_ranges(ranges), encode(&original));
+        }
+
+        // far beyond anything that could be encoded from the dense form
+        let enc = encode_ranges(vec![0..2, 2..3, 5..5, 1_000_000_000_000..1_000_000_000_001]);
+        assert_eq!(
+            RunIter::new(&enc).collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![(true, 3), (false, 1_000_000_000_000 - 3), (true, 1)]
+        );
+        assert!(is_canonical(&enc));
+    }
+
+    #[test]
+    fn test_rle_plus_byte_layout() {
+        let cases = vec![
+            (bitvec![LittleEndian; 0; 8], vec![0b0100_0100]),
+            (
+                bitvec![LittleEndian; 0, 0, 0, 0, 1, 0, 0, 0],
+                vec![0b1010_0100, 0b0000_1110],
+            ),
+            (
+                // the last byte only holds the end of the varint
+                bitvec![LittleEndian; 1; 16],
+                vec![0b1000_0001, 0b0000_0000],
+            ),
+            (BitVec::new(), vec![]),
+        ];
+
+        for (i, case) in cases.into_iter().enumerate() {
+            assert_eq!(encode_to_bytes(&case.0), case.1, "case: {}", i);
+            assert_eq!(
+                decode_from_bytes(&case.1),
+                Ok(case.0.clone()),
+                "case: {}",
+                i
+            );
+            assert_eq!(
+                decode_from_bytes_canonical(&case.1),
+                Ok(case.0),
+                "case: {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_rle_plus_byte_padding() {
+        let expected = bitvec![LittleEndian; 0; 8];
+
+        // a whole byte of padding
+        let bytes = [0b0100_0100, 0];
+        assert_eq!(decode_from_bytes(&bytes), Ok(expected.clone()));
+        assert_eq!(
+            decode_from_bytes_canonical(&bytes),
+            Err(RlePlusError::NonCanonical { offset: 7 })
+        );
+
+        // set bits after the last block are blocks, not padding
+        let bytes = [0b1100_0100];
+        let mut expected_long = expected.clone();
+        expected_long.push(true);
+        assert_eq!(decode_from_bytes(&bytes), Ok(expected_long));
+
+        // just a header
+        assert_eq!(decode_from_bytes(&[0b0000_0001]), Ok(BitVec::new()));
+        assert_eq!(
+            decode_from_bytes_canonical(&[0b0000_0001]),
+            Err(RlePlusError::NonCanonical { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn test_rle_plus_decode_limits() {
+        // a single long block of 2^63 bits
+        let mut bomb = bitvec![LittleEndian; 1, 0, 0];
+        let mut v = [0u8; 10];
+        let varint = unsigned_varint::encode::u64(1 << 63, &mut v);
+        bomb.extend(BitVec::<LittleEndian, u8>::from(varint).iter());
+
+        let limits = DecodeLimits {
+            max_len: 1 << 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_runs(RunIter::new(&bomb).with_limits(limits)),
+            Err(RlePlusError::LimitExceeded { offset: 1 })
+        );
+
+        let bytes: Vec<u8> = bomb.into();
+        assert_eq!(
+            decode_from_bytes_with_limits(&bytes, limits),
+            Err(RlePlusError::LimitExceeded { offset: 1 })
+        );
+        // too large to allocate
+        assert_eq!(
+            decode_from_bytes(&bytes),
+            Err(RlePlusError::LengthOverflow { offset: 1 })
+        );
+
+        let enc = encode(&bitvec![LittleEndian; 0, 0, 1, 1, 1, 0, 1, 1]);
+        let cases = vec![
+            (DecodeLimits::default(), None),
+            (
+                DecodeLimits {
+                    max_len: 7,
+                    ..Default::default()
+                },
+                Some(14),
+            ),
+            (
+                DecodeLimits {
+                    max_runs: 3,
+                    ..Default::default()
+                },
+                Some(14),
+            ),
+            (
+                DecodeLimits {
+                    max_ones: 2,
+                    ..Default::default()
+                },
+                Some(7),
+            ),
+            (
+                DecodeLimits {
+                    max_len: 8,
+                    max_runs: 4,
+                    max_ones: 5,
+                },
+                None,
+            ),
+        ];
+
+        for (i, (limits, offset)) in cases.into_iter().enumerate() {
+            let res = decode_runs(RunIter::new(&enc).with_limits(limits));
+            match offset {
+                Some(offset) => assert_eq!(
+                    res,
+                    Err(RlePlusError::LimitExceeded { offset }),
+                    "case: {}",
+                    i
+                ),
+                None => assert!(res.is_ok(), "case: {}", i),
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_fast() {
+        let mut rng = XorShiftRng::from_seed([14u8; 16]);
+
+        for _i in 0..1000 {
+            let len: usize = rng.gen_range(0, 200);
+
+            // runs of all sizes, crossing word boundaries
+            let mut original = BitVec::<LittleEndian, u8>::new();
+            let mut value = rng.gen();
+            while original.len() < len * 8 {
+                let run = match rng.gen_range(0, 3) {
+                    0 => rng.gen_range(1, 4),
+                    1 => rng.gen_range(4, 70),
+                    _ => rng.gen_range(70, 300),
+                };
+                original.extend((0..run).map(|_| value));
+                value = !value;
+            }
+            let cut = rng.gen_range(0, original.len() + 1);
+            original.truncate(cut);
+
+            assert_eq!(encode_fast(&original), encode(&original));
+        }
+
+        // garbage in the unused bits of the last byte
+        let mut bytes = vec![0b1111_0000u8];
+        let mut bits: BitVec<LittleEndian, u8> = bytes.clone().into();
+        bits.truncate(4);
+        assert_eq!(encode_fast(&bits), encode(&bitvec![LittleEndian; 0; 4]));
+
+        bytes[0] = 0b0000_1111;
+        let mut bits: BitVec<LittleEndian, u8> = bytes.into();
+        bits.truncate(4);
+        assert_eq!(encode_fast(&bits), encode(&bitvec![LittleEndian; 1; 4]));
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let mut rng = XorShiftRng::from_seed([15u8; 16]);
+
+        for _i in 0..200 {
+            let len: usize = rng.gen_range(0, 100);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+            let original: BitVec<LittleEndian, u8> = src.clone().into();
+            let encoded = encode(&original);
+
+            // garbage is overwritten
+            let mut buf = vec![0b0101_0101; len];
+            assert_eq!(decode_into(RunIter::new(&encoded), &mut buf), Ok(len * 8));
+            assert_eq!(buf, src);
+
+            if len > 0 && src.last() != Some(&0) {
+                let mut buf = vec![0; len - 1];
+                match decode_into(RunIter::new(&encoded), &mut buf) {
+                    Err(RlePlusError::LimitExceeded { .. }) => {}
+                    res => panic!("unexpected result: {:?}", res),
+                }
+            }
+        }
+
+        // the limits of the runs are kept
+        let encoded = encode(&bitvec![LittleEndian; 0, 1, 1, 1, 1]);
+        let limits = DecodeLimits {
+            max_ones: 1,
+            ..Default::default()
+        };
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            decode_into(RunIter::new(&encoded).with_limits(limits), &mut buf),
+            Err(RlePlusError::LimitExceeded { offset: 2 })
+        );
+        let limits = DecodeLimits {
+            max_len: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_into(RunIter::new(&encoded).with_limits(limits), &mut buf),
+            Err(RlePlusError::LimitExceeded { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn test_rle_plus_roundtrip_small() {
+        let mut rng = XorShiftRng::from_seed([1u8; 16]);
+
+        for _i in 0..10000 {
+            let len: usize = rng.gen_range(0, 1000);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+
+            let encoded = encode(&original);
+            let decoded = decode(&encoded);
+
+            assert_eq!(original, decoded);
+            assert_eq!(try_decode_canonical(&encoded), Ok(decoded.clone()));
+
+            let ones = original.count_ones() as u64;
+            assert_eq!(count_ones(&encoded), Ok(ones));
+            assert_eq!(count_zeros(&encoded), Ok(original.len() as u64 - ones));
+            assert_eq!(decoded_len(&encoded), Ok(original.len() as u64));
+
+            let bytes = encode_to_bytes(&original);
+            assert_eq!(decode_from_bytes_canonical(&bytes), Ok(decoded));
+        }
+    }
+
+    #[test]
+    fn test_rle_plus_roundtrip_large() {
+        let mut rng = XorShiftRng::from_seed([2u8; 16]);
+
+        for _i in 0..100 {
+            let len: usize = rng.gen_range(0, 100000);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+
+            let encoded = encode(&original);
+            let decoded = decode(&encoded);
+
+            assert_eq!(original, decoded);
+
+            let ones = original.count_ones() as u64;
+            assert_eq!(count_ones(&encoded), Ok(ones));
+            assert_eq!(count_zeros(&encoded), Ok(original.len() as u64 - ones));
+            assert_eq!(decoded_len(&encoded), Ok(original.len() as u64));
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Set operations, computed on the runs of RLE+ encoded bitsets.
+//!
+//! Bitsets of different lengths are treated as if the shorter one was extended with `0`s,
+//! so the result is as long as the longer input.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use bitvec::*;
+
+use super::iter::RunIter;
+use super::writer::RunEncoder;
+use super::RlePlusError;
+
+/// Union of two RLE+ encoded bitsets, returned in its canonical encoding.
+pub fn union(
+    a: &BitVec<LittleEndian, u8>,
+    b: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    combine(a, b, |a, b| a | b)
+}
+
+/// Intersection of two RLE+ encoded bitsets, returned in its canonical encoding.
+pub fn intersection(
+    a: &BitVec<LittleEndian, u8>,
+    b: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    combine(a, b, |a, b| a & b)
+}
+
+/// Difference of two RLE+ encoded bitsets, the bits set in `a` but not in `b`, returned in its
+/// canonical encoding.
+pub fn difference(
+    a: &BitVec<LittleEndian, u8>,
+    b: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    combine(a, b, |a, b| a & !b)
+}
+
+/// Symmetric difference of two RLE+ encoded bitsets, returned in its canonical encoding.
+pub fn xor(
+    a: &BitVec<LittleEndian, u8>,
+    b: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    combine(a, b, |a, b| a ^ b)
+}
+
+/// Union of any number of RLE+ encoded bitsets, returned in its canonical encoding.
+///
+/// All encodings are merged in a single pass over their runs, instead of one union at a time.
+///
+/// ```
+/// use bitsets::rleplus::{decode, encode, union_all};
+/// use bitvec::*;
+///
+/// let a = encode(&bitvec![LittleEndian; 1, 0, 0]);
+/// let b = encode(&bitvec![LittleEndian; 0, 0, 1, 1]);
+/// let c = encode(&bitvec![LittleEndian; 0, 1]);
+/// assert_eq!(decode(&union_all(&[a, b, c]).unwrap()), bitvec![LittleEndian; 1, 1, 1, 1]);
+/// ```
+pub fn union_all<'a, I>(encs: I) -> Result<BitVec<LittleEndian, u8>, RlePlusError>
+where
+    I: IntoIterator<Item = &'a BitVec<LittleEndian, u8>>,
+{
+    combine_all(encs, |set, _| set > 0)
+}
+
+/// Intersection of any number of RLE+ encoded bitsets, returned in its canonical encoding.
+///
+/// All encodings are merged in a single pass over their runs, instead of one intersection at a time.
+pub fn intersect_all<'a, I>(encs: I) -> Result<BitVec<LittleEndian, u8>, RlePlusError>
+where
+    I: IntoIterator<Item = &'a BitVec<LittleEndian, u8>>,
+{
+    combine_all(encs, |set, total| set == total)
+}
+
+/// Merge the runs of all encodings, with `op` deciding the value of a bit from the number of encodings
+/// it is set in, and the total number of encodings.
+///
+/// The encodings are kept in a heap, ordered by the end of their current run.
+fn combine_all<'a, I, F>(encs: I, op: F) -> Result<BitVec<LittleEndian, u8>, RlePlusError>
+where
+    I: IntoIterator<Item = &'a BitVec<LittleEndian, u8>>,
+    F: Fn(usize, usize) -> bool,
+{
+    let mut cursors: Vec<RunCursor> = encs
+        .into_iter()
+        .map(|enc| RunCursor::new(RunIter::new(enc)))
+        .collect();
+    let total = cursors.len();
+
+    let mut heap = BinaryHeap::with_capacity(total);
+    // number of encodings, whose current run is set
+    let mut set = 0;
+
+    for (i, cursor) in cursors.iter_mut().enumerate() {
+        if let Some((value, len)) = cursor.current()? {
+            heap.push(Reverse((len, i)));
+            set += value as usize;
+        }
+    }
+
+    let mut encoder = RunEncoder::new();
+    let mut pos = 0;
+
+    while let Some(&Reverse((end, _))) = heap.peek() {
+        encoder.push_run(op(set, total), end - pos);
+        pos = end;
+
+        // move on all encodings, whose current run ends here
+        while let Some(&Reverse((run_end, i))) = heap.peek() {
+            if run_end != end {
+                break;
+            }
+            heap.pop();
+
+            let cursor = &mut cursors[i];
+            if let Some((value, len)) = cursor.current()? {
+                set -= value as usize;
+                cursor.advance(len);
+            }
+            if let Some((value, len)) = cursor.current()? {
+                heap.push(Reverse((end + len, i)));
+                set += value as usize;
+            }
+        }
+    }
+
+    Ok(encoder.finish())
+}
+
+/// Merge the runs of both encodings, combining the bits with `op`.
+fn combine<F>(
+    a: &BitVec<LittleEndian, u8>,
+    b: &BitVec<LittleEndian, u8>,
+    op: F,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError>
+where
+    F: Fn(bool, bool) -> bool,
+{
+    let mut a = RunCursor::new(RunIter::new(a));
+    let mut b = RunCursor::new(RunIter::new(b));
+    let mut encoder = RunEncoder::new();
+
+    loop {
+        let (len, value) = match (a.current()?, b.current()?) {
+            (Some((a, a_len)), Some((b, b_len))) => (a_len.min(b_len), op(a, b)),
+            // the exhausted side is extended with 0s
+            (Some((a, a_len)), None) => (a_len, op(a, false)),
+            (None, Some((b, b_len))) => (b_len, op(false, b)),
+            (None, None) => break,
+        };
+
+        encoder.push_run(value, len);
+        a.advance(len);
+        b.advance(len);
+    }
+
+    Ok(encoder.finish())
+}
+
+/// Wraps a [`RunIter`], allowing to consume runs partially.
+#[derive(Debug, Clone)]
+pub(crate) struct RunCursor<'a> {
+    runs: RunIter<'a>,
+    /// The current run, with the part not yet consumed.
+    run: Option<(bool, u64)>,
+}
+
+impl<'a> RunCursor<'a> {
+    pub fn new(runs: RunIter<'a>) -> Self {
+        RunCursor { runs, run: None }
+    }
+
+    /// The remainder of the current run, `None` once all runs are consumed.
+    ///
+    /// Never returns empty runs.
+    pub fn current(&mut self) -> Result<Option<(bool, u64)>, RlePlusError> {
+        while self.run.map_or(true, |(_, len)| len == 0) {
+            match self.runs.next() {
+                Some(run) => self.run = Some(run?),
+                None => {
+                    self.run = None;
+                    break;
+                }
+            }
+        }
+
+        Ok(self.run)
+    }
+
+    /// Consume `n` bits of the current run, which must be at least `n` bits long.
+    ///
+    /// Does nothing once all runs are consumed.
+    pub fn advance(&mut self, n: u64) {
+        if let Some((_, ref mut len)) = self.run {
+            debug_assert!(n <= *len);
+            *len -= n;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::tests::random_bitvec;
+    use crate::rleplus::{decode, encode, is_canonical};
+
+    type SetOp = fn(
+        &BitVec<LittleEndian, u8>,
+        &BitVec<LittleEndian, u8>,
+    ) -> Result<BitVec<LittleEndian, u8>, RlePlusError>;
+    type BitOp = fn(bool, bool) -> bool;
+
+    #[test]
+    fn test_set_operations() {
+        let mut rng = XorShiftRng::from_seed([7u8; 16]);
+
+        let ops: Vec<(SetOp, BitOp)> = vec![
+            (union, |a, b| a | b),
+            (intersection, |a, b| a & b),
+            (difference, |a, b| a & !b),
+            (xor, |a, b| a ^ b),
+        ];
+
+        for _i in 0..1000 {
+            let a = random_bitvec(&mut rng, 50);
+            let b = random_bitvec(&mut rng, 50);
+
+            let enc_a = encode(&a);
+            let enc_b = encode(&b);
+
+            for (i, (op, bit_op)) in ops.iter().enumerate() {
+                let expected: BitVec<LittleEndian, u8> = (0..a.len().max(b.len()))
+                    .map(|j| bit_op(a.get(j).unwrap_or(false), b.get(j).unwrap_or(false)))
+                    .collect();
+
+                let res = op(&enc_a, &enc_b).unwrap();
+                assert!(is_canonical(&res), "op: {}", i);
+                assert_eq!(decode(&res), expected, "op: {}", i);
+                assert_eq!(res, encode(&expected), "op: {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_combine_all() {
+        let mut rng = XorShiftRng::from_seed([21u8; 16]);
+
+        for _i in 0..200 {
+            let k = rng.gen_range(0, 8);
+            let encs: Vec<_> = (0..k)
+                .map(|_| encode(&random_bitvec(&mut rng, 50)))
+                .collect();
+
+            let expected_union = encs
+                .iter()
+                .try_fold(BitVec::new(), |acc, enc| union(&acc, enc));
+            assert_eq!(union_all(&encs), expected_union);
+
+            let expected_intersection = match encs.split_first() {
+                Some((first, rest)) => rest
+                    .iter()
+                    .try_fold(first.clone(), |acc, enc| intersection(&acc, enc)),
+                None => Ok(BitVec::new()),
+            };
+            assert_eq!(intersect_all(&encs), expected_intersection);
+        }
+    }
+
+    #[test]
+    fn test_set_operations_errors() {
+        let a = encode(&bitvec![LittleEndian; 1, 0, 1]);
+        let b = bitvec![LittleEndian; 0, 1, 0];
+
+        assert_eq!(union(&a, &b), Err(RlePlusError::TrailingBits { offset: 2 }));
+    }
+}
@@ -0,0 +1,365 @@
+//! Rank, select and membership queries on RLE+ encoded bitsets.
+//!
+//! Without an index, each query walks the runs from the start of the encoding, up to the queried position.
+//! A [`SkipIndex`] stores checkpoints every few runs, so that rank and select queries can start from the
+//! closest checkpoint instead.
+
+use std::cmp::Ordering;
+
+use bitvec::*;
+
+use super::iter::RunIter;
+use super::RlePlusError;
+
+/// Number of set bits before position `i`, i.e. in `0..i`.
+pub fn rank(enc: &BitVec<LittleEndian, u8>, i: u64) -> Result<u64, RlePlusError> {
+    rank_from(RunIter::new(enc), 0, 0, i)
+}
+
+/// Position of the `k`-th set bit, counting from `0`, or `None` if fewer bits are set.
+pub fn select(enc: &BitVec<LittleEndian, u8>, k: u64) -> Result<Option<u64>, RlePlusError> {
+    select_from(RunIter::new(enc), 0, 0, k)
+}
+
+/// Check if the bit at position `i` is set.
+pub fn contains(enc: &BitVec<LittleEndian, u8>, i: u64) -> Result<bool, RlePlusError> {
+    let mut pos = 0;
+
+    for run in RunIter::new(enc) {
+        let (value, len) = run?;
+        pos += len;
+
+        if i < pos {
+            return Ok(value);
+        }
+    }
+
+    // everything after the end is not set
+    Ok(false)
+}
+
+/// Check for each of the positions in `indices`, which must be sorted, if the bit at it is set.
+///
+/// All queries are answered in a single pass over the runs.
+///
+/// # Panics
+///
+/// Panics if `indices` is not sorted.
+pub fn contains_many(
+    enc: &BitVec<LittleEndian, u8>,
+    indices: &[u64],
+) -> Result<Vec<bool>, RlePlusError> {
+    let mut res = Vec::with_capacity(indices.len());
+    let mut indices = indices.iter().cloned().peekable();
+
+    // start of the current run
+    let mut pos = 0;
+    let mut last = 0;
+
+    for run in RunIter::new(enc) {
+        let (value, len) = run?;
+        pos += len;
+
+        // answer all queries within this run
+        while let Some(&i) = indices.peek() {
+            if i >= pos {
+                break;
+            }
+            assert!(i >= last, "indices must be sorted");
+            last = i;
+
+            res.push(value);
+            indices.next();
+        }
+
+        if indices.peek().is_none() {
+            break;
+        }
+    }
+
+    // everything after the end is not set
+    for i in indices {
+        assert!(i >= last, "indices must be sorted");
+        last = i;
+
+        res.push(false);
+    }
+
+    Ok(res)
+}
+
+/// Continue counting set bits before `i`, starting with a run at position `pos`, after `ones` set bits.
+fn rank_from(runs: RunIter, mut pos: u64, mut ones: u64, i: u64) -> Result<u64, RlePlusError> {
+    for run in runs {
+        let (value, len) = run?;
+
+        if pos + len > i {
+            if value {
+                ones += i - pos;
+            }
+            break;
+        }
+
+        pos += len;
+        if value {
+            ones += len;
+        }
+    }
+
+    Ok(ones)
+}
+
+/// Continue looking for the `k`-th set bit, starting with a run at position `pos`, after `ones` set bits.
+fn select_from(
+    runs: RunIter,
+    mut pos: u64,
+    mut ones: u64,
+    k: u64,
+) -> Result<Option<u64>, RlePlusError> {
+    for run in runs {
+        let (value, len) = run?;
+
+        if value {
+            if ones + len > k {
+                return Ok(Some(pos + (k - ones)));
+            }
+            ones += len;
+        }
+        pos += len;
+    }
+
+    Ok(None)
+}
+
+/// The state at the start of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Checkpoint {
+    /// Bit position of the block in the encoding.
+    offset: usize,
+    /// Value of the run.
+    value: bool,
+    /// Position of the run in the decoded bitset.
+    pos: u64,
+    /// Number of set bits before the run.
+    ones: u64,
+}
+
+/// An index over an RLE+ encoded bitset, speeding up [`rank`] and [`select`] queries.
+///
+/// The index is kept separate from the encoding, and must only be used with the encoding it was built for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkipIndex {
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl SkipIndex {
+    /// Build an index for the given encoding, with a checkpoint every `interval` runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is `0`.
+    pub fn new(enc: &BitVec<LittleEndian, u8>, interval: usize) -> Result<Self, RlePlusError> {
+        assert!(interval > 0, "interval must be at least 1");
+
+        let mut checkpoints = Vec::new();
+        let mut pos = 0;
+        let mut ones = 0;
+
+        let mut runs = RunIter::new(enc);
+        for i in 0.. {
+            let offset = runs.position();
+            let (value, len) = match runs.next() {
+                Some(run) => run?,
+                None => break,
+            };
+
+            if i % interval == 0 {
+                checkpoints.push(Checkpoint {
+                    offset,
+                    value,
+                    pos,
+                    ones,
+                });
+            }
+
+            pos += len;
+            if value {
+                ones += len;
+            }
+        }
+
+        Ok(SkipIndex { checkpoints })
+    }
+
+    /// Number of set bits before position `i`, see [`rank`].
+    pub fn rank(&self, enc: &BitVec<LittleEndian, u8>, i: u64) -> Result<u64, RlePlusError> {
+        match self.last_checkpoint(|c| c.pos <= i) {
+            Some(c) => rank_from(
+                RunIter::resume(enc, c.offset, c.value, c.pos),
+                c.pos,
+                c.ones,
+                i,
+            ),
+            None => rank(enc, i),
+        }
+    }
+
+    /// Position of the `k`-th set bit, see [`select`].
+    pub fn select(
+        &self,
+        enc: &BitVec<LittleEndian, u8>,
+        k: u64,
+    ) -> Result<Option<u64>, RlePlusError> {
+        match self.last_checkpoint(|c| c.ones <= k) {
+            Some(c) => select_from(
+                RunIter::resume(enc, c.offset, c.value, c.pos),
+                c.pos,
+                c.ones,
+                k,
+            ),
+            None => select(enc, k),
+        }
+    }
+
+    /// The last checkpoint matching `before`, which must hold for a prefix of all checkpoints.
+    fn last_checkpoint<F>(&self, before: F) -> Option<&Checkpoint>
+    where
+        F: Fn(&Checkpoint) -> bool,
+    {
+        let idx = self
+            .checkpoints
+            .binary_search_by(|c| {
+                if before(c) {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            })
+            .unwrap_err();
+
+        if idx == 0 {
+            None
+        } else {
+            Some(&self.checkpoints[idx - 1])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::encode;
+
+    #[test]
+    fn test_rank_select() {
+        let mut rng = XorShiftRng::from_seed([9u8; 16]);
+
+        for _i in 0..200 {
+            let len: usize = rng.gen_range(0, 30);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+            let ones: Vec<u64> = original
+                .iter()
+                .enumerate()
+                .filter(|(_, bit)| *bit)
+                .map(|(i, _)| i as u64)
+                .collect();
+
+            let encoded = encode(&original);
+            let indices = vec![
+                SkipIndex::new(&encoded, 1).unwrap(),
+                SkipIndex::new(&encoded, 7).unwrap(),
+            ];
+
+            for i in 0..original.len() as u64 + 8 {
+                let expected = ones.iter().filter(|&&one| one < i).count() as u64;
+                assert_eq!(rank(&encoded, i), Ok(expected));
+                for index in &indices {
+                    assert_eq!(index.rank(&encoded, i), Ok(expected));
+                }
+            }
+
+            for k in 0..ones.len() as u64 + 8 {
+                let expected = ones.get(k as usize).cloned();
+                assert_eq!(select(&encoded, k), Ok(expected));
+                for index in &indices {
+                    assert_eq!(index.select(&encoded, k), Ok(expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut rng = XorShiftRng::from_seed([10u8; 16]);
+
+        for _i in 0..200 {
+            let len: usize = rng.gen_range(0, 30);
+
+            let mut src = vec![0u8; len];
+            rng.fill_bytes(&mut src);
+
+            let original: BitVec<LittleEndian, u8> = src.into();
+            let encoded = encode(&original);
+
+            let mut indices: Vec<u64> = (0..20)
+                .map(|_| rng.gen_range(0, original.len() as u64 + 8))
+                .collect();
+            indices.sort();
+
+            let expected: Vec<bool> = indices
+                .iter()
+                .map(|&i| original.get(i as usize).unwrap_or(false))
+                .collect();
+
+            for (&i, &bit) in indices.iter().zip(expected.iter()) {
+                assert_eq!(contains(&encoded, i), Ok(bit));
+            }
+            assert_eq!(contains_many(&encoded, &indices), Ok(expected));
+        }
+
+        // stops before reaching the malformed end
+        let enc = bitvec![LittleEndian; 1, 0, 1, 1, 1, 0, 0, 0];
+        assert_eq!(contains(&enc, 1), Ok(true));
+        assert_eq!(
+            contains(&enc, 3),
+            Err(RlePlusError::TrailingBits { offset: 7 })
+        );
+    }
+
+    #[test]
+    fn test_skip_index_checkpoints() {
+        // runs: 3x0, 1x1, 16x0, 2x1
+        let enc = encode(
+            &bitvec![LittleEndian; 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1],
+        );
+        let index = SkipIndex::new(&enc, 2).unwrap();
+
+        assert_eq!(
+            index.checkpoints,
+            vec![
+                Checkpoint {
+                    offset: 1,
+                    value: false,
+                    pos: 0,
+                    ones: 0,
+                },
+                Checkpoint {
+                    offset: 8,
+                    value: false,
+                    pos: 4,
+                    ones: 1,
+                },
+            ]
+        );
+        assert_eq!(index.rank(&enc, 21), Ok(2));
+        assert_eq!(index.select(&enc, 1), Ok(Some(20)));
+    }
+}
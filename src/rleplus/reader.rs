@@ -0,0 +1,80 @@
+/// A cursor over the bits of an encoding, stored least significant bit first in a byte slice.
+#[derive(Debug, Clone)]
+pub(crate) struct BitReader<'a> {
+    buf: &'a [u8],
+    /// Number of valid bits in `buf`.
+    len: usize,
+    /// Position of the next bit to read.
+    pos: usize,
+    /// Position of the first bit of `buf` in the whole encoding, if `buf` only holds a part of it.
+    base: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buf: &'a [u8], len: usize) -> Self {
+        Self::with_base(buf, len, 0)
+    }
+
+    /// Read from a part of an encoding, that starts at position `base` of the whole encoding.
+    ///
+    /// All positions are relative to the whole encoding.
+    pub fn with_base(buf: &'a [u8], len: usize, base: usize) -> Self {
+        debug_assert!(len <= buf.len() * 8);
+
+        BitReader {
+            buf,
+            len,
+            pos: 0,
+            base,
+        }
+    }
+
+    /// Position of the next bit to read.
+    pub fn position(&self) -> usize {
+        self.base + self.pos
+    }
+
+    /// Move to the given position, which must be within the encoding.
+    pub fn seek(&mut self, pos: usize) {
+        debug_assert!(pos >= self.base && pos - self.base <= self.len);
+
+        self.pos = pos - self.base;
+    }
+
+    /// Number of bits left to read.
+    pub fn remaining(&self) -> usize {
+        self.len - self.pos
+    }
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        if self.pos >= self.len {
+            return None;
+        }
+
+        let bit = self.buf[self.pos / 8] & (1 << (self.pos % 8)) != 0;
+        self.pos += 1;
+
+        Some(bit)
+    }
+
+    /// Read the next `n` bits, with `n <= 8`, the first bit read ending up as the least significant one.
+    pub fn read_bits(&mut self, n: usize) -> Option<u8> {
+        debug_assert!(n <= 8);
+
+        if self.remaining() < n {
+            return None;
+        }
+
+        let byte = self.pos / 8;
+        let shift = self.pos % 8;
+
+        // the bits might be spread over two bytes
+        let mut bits = u16::from(self.buf[byte]) >> shift;
+        if shift + n > 8 {
+            bits |= u16::from(self.buf[byte + 1]) << (8 - shift);
+        }
+        self.pos += n;
+
+        Some((bits & ((1 << n) - 1)) as u8)
+    }
+}
@@ -0,0 +1,174 @@
+//! [`serde`] support for [`BitField`], enabled by the `serde` feature.
+//!
+//! Human readable formats, like JSON, get the bitfield in the [text format](index.html#text-format), i.e.
+//! the set bits as a list of inclusive ranges, e.g. `"0-9,15,20-30"`, followed by the length if the
+//! bitfield ends with `0`s, e.g. `"3-4/7"`.
+//! Binary formats get the encoding as bytes, as described in [Byte Layout](index.html#byte-layout).
+//!
+//! Deserializing fails for ranges which are not sorted or overlap, and for encodings which are not in their
+//! [canonical form](index.html#canonical-form).
+
+use std::fmt;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use super::BitField;
+
+impl Serialize for BitField {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BitField {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(RangesVisitor)
+        } else {
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+}
+
+struct RangesVisitor;
+
+impl<'de> Visitor<'de> for RangesVisitor {
+    type Value = BitField;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of ranges, like \"0-9,15,20-30/40\"")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        v.parse().map_err(E::custom)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = BitField;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an RLE+ encoding in bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        BitField::from_bytes(v).map_err(E::custom)
+    }
+
+    // for formats without a byte type
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // the size hint comes from the input, so only trust it up to a point
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+
+        self.visit_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitvec::*;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    #[test]
+    fn test_serde_json() {
+        let bf = BitField::from_ranges(vec![0..10, 15..16, 20..31]);
+        let json = serde_json::to_string(&bf).unwrap();
+        assert_eq!(json, "\"0-9,15,20-30\"");
+        assert_eq!(serde_json::from_str::<BitField>(&json).unwrap(), bf);
+
+        assert_eq!(serde_json::to_string(&BitField::new()).unwrap(), "\"\"");
+        assert_eq!(
+            serde_json::from_str::<BitField>("\"\"").unwrap(),
+            BitField::new()
+        );
+
+        // trailing 0s are kept
+        let bf = BitField::from_bits(&bitvec![LittleEndian; 0, 0, 0, 1, 1, 0, 0]);
+        let json = serde_json::to_string(&bf).unwrap();
+        assert_eq!(json, "\"3-4/7\"");
+        assert_eq!(serde_json::from_str::<BitField>(&json).unwrap(), bf);
+
+        for invalid in &[
+            "\"3,1\"",
+            "\"0-5,5\"",
+            "\"5-2\"",
+            "\"a\"",
+            "\"1,\"",
+            "\"-1\"",
+            "\"0-18446744073709551615\"",
+        ] {
+            assert!(
+                serde_json::from_str::<BitField>(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_serde_cbor() {
+        let bf = BitField::from_indices(vec![4]);
+        let cbor = serde_cbor::to_vec(&bf).unwrap();
+        // a byte string of 1 byte
+        assert_eq!(cbor, vec![0x41, 0b1010_0100]);
+        assert_eq!(serde_cbor::from_slice::<BitField>(&cbor).unwrap(), bf);
+
+        // not canonical, a run of 1 written as a short block
+        assert!(serde_cbor::from_slice::<BitField>(&[0x41, 0b0000_0100]).is_err());
+
+        // an array claiming 2^64 - 1 elements
+        let huge = [0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+        assert!(serde_cbor::from_slice::<BitField>(&huge).is_err());
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut rng = XorShiftRng::from_seed([22u8; 16]);
+
+        for _i in 0..200 {
+            let mut pos = rng.gen_range(0, 10);
+            let mut indices = Vec::new();
+            for _ in 0..rng.gen_range(0, 50) {
+                indices.push(pos);
+                pos += rng.gen_range(1, 20);
+            }
+            let bf = BitField::from_indices(indices);
+
+            let json = serde_json::to_string(&bf).unwrap();
+            assert_eq!(serde_json::from_str::<BitField>(&json).unwrap(), bf);
+
+            let cbor = serde_cbor::to_vec(&bf).unwrap();
+            assert_eq!(cbor, bf.to_cbor());
+            assert_eq!(serde_cbor::from_slice::<BitField>(&cbor).unwrap(), bf);
+        }
+    }
+}
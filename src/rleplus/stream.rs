@@ -0,0 +1,536 @@
+//! Streaming encoding and decoding of RLE+ encoded bitsets in their [byte layout](index.html#byte-layout).
+
+use std::io::{self, Read, Write};
+
+use super::iter::{BlockDecoder, DecodeLimits};
+use super::reader::BitReader;
+use super::writer::RunEncoder;
+use super::RlePlusError;
+
+/// Number of complete bytes buffered, before they are written out, and number of bytes read at once.
+const BUFFER_SIZE: usize = 4096;
+
+/// Encodes a bitset incrementally, writing the canonical RLE+ encoding to `W`.
+///
+/// Bits are pushed in order, as single bits, runs, or the indices of set bits. Completed bytes are
+/// buffered and written out in chunks, so only the last few bytes and the current run are kept in memory.
+/// Call [`RlePlusWriter::finish`] to write the last block and its padding.
+///
+/// ```
+/// use bitsets::rleplus::{decode_from_bytes, RlePlusWriter};
+///
+/// let mut writer = RlePlusWriter::new(Vec::new());
+/// writer.push_run(false, 3).unwrap();
+/// writer.push_index(5).unwrap();
+/// writer.push_bit(true).unwrap();
+/// let bytes = writer.finish().unwrap();
+///
+/// let bits = decode_from_bytes(&bytes).unwrap();
+/// assert_eq!(bits.iter().collect::<Vec<_>>(), vec![false, false, false, false, false, true, true]);
+/// ```
+#[derive(Debug)]
+pub struct RlePlusWriter<W: Write> {
+    inner: W,
+    encoder: RunEncoder,
+    /// Number of bits pushed so far.
+    pos: u64,
+}
+
+impl<W: Write> RlePlusWriter<W> {
+    pub fn new(inner: W) -> Self {
+        RlePlusWriter {
+            inner,
+            encoder: RunEncoder::new(),
+            pos: 0,
+        }
+    }
+
+    /// Number of bits pushed so far, i.e. the position of the next bit.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Append a single bit.
+    pub fn push_bit(&mut self, bit: bool) -> io::Result<()> {
+        self.push_run(bit, 1)
+    }
+
+    /// Append `len` bits of the given value.
+    pub fn push_run(&mut self, value: bool, len: u64) -> io::Result<()> {
+        self.pos = self
+            .pos
+            .checked_add(len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bitset length overflow"))?;
+        self.encoder.push_run(value, len);
+
+        if self.encoder.writer_mut().complete_bytes() >= BUFFER_SIZE {
+            self.encoder.writer_mut().drain_to(&mut self.inner)?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the bit at index `i`, appending `0`s up to it.
+    ///
+    /// Fails if `i` is before the current position.
+    pub fn push_index(&mut self, i: u64) -> io::Result<()> {
+        if i < self.pos {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index before the current position",
+            ));
+        }
+
+        self.push_run(false, i - self.pos)?;
+        self.push_run(true, 1)
+    }
+
+    /// Write the remaining blocks and padding, and return the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut inner = self.inner;
+
+        inner.write_all(&self.encoder.finish_writer().into_bytes())?;
+        inner.flush()?;
+
+        Ok(inner)
+    }
+}
+
+/// Decodes a bitset incrementally, reading its RLE+ encoding from `R`.
+///
+/// Yields the runs of the bitset as `(value, length)` pairs, like [`RunIter::from_bytes`], but reads
+/// bytes only as needed to decode the next block. Decoding errors are returned as [`io::Error`]s of
+/// kind [`io::ErrorKind::InvalidData`], wrapping the [`RlePlusError`].
+///
+/// After an error was returned, the iterator is exhausted.
+///
+/// [`RunIter::from_bytes`]: struct.RunIter.html#method.from_bytes
+///
+/// ```
+/// use bitsets::rleplus::RlePlusReader;
+///
+/// let bytes: &[u8] = &[0b0100_0100];
+/// let runs = RlePlusReader::new(bytes).collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(runs, vec![(false, 8)]);
+/// ```
+#[derive(Debug)]
+pub struct RlePlusReader<R: Read> {
+    inner: R,
+    /// Bytes read, but not yet fully decoded.
+    buf: Vec<u8>,
+    /// Number of `0` bytes read after `buf`, which are only counted, not stored.
+    zeros: usize,
+    /// Bit position in `buf` after the last set bit, everything from there on is `0`.
+    end: usize,
+    /// Bit position of the next block in `buf`.
+    pos: usize,
+    /// Position of the first bit of `buf` in the whole encoding.
+    base: usize,
+    eof: bool,
+    /// Number of blocks of only `0`s passed over, which are either padding, or runs of length `0`.
+    skipped: usize,
+    /// Position of the first block passed over in the whole encoding.
+    skipped_at: usize,
+    /// Set once the header was read.
+    decoder: Option<BlockDecoder>,
+    limits: DecodeLimits,
+    canonical: bool,
+    done: bool,
+}
+
+impl<R: Read> RlePlusReader<R> {
+    /// Read any encoding following the RLE+ grammar.
+    pub fn new(inner: R) -> Self {
+        RlePlusReader {
+            inner,
+            buf: Vec::new(),
+            zeros: 0,
+            end: 0,
+            pos: 0,
+            base: 0,
+            eof: false,
+            skipped: 0,
+            skipped_at: 0,
+            decoder: None,
+            limits: DecodeLimits::default(),
+            canonical: false,
+            done: false,
+        }
+    }
+
+    /// Read an encoding, failing if it is not in its [canonical form](index.html#canonical-form).
+    pub fn canonical(inner: R) -> Self {
+        let mut reader = Self::new(inner);
+        reader.canonical = true;
+
+        reader
+    }
+
+    /// Fail with [`RlePlusError::LimitExceeded`] as soon as the runs read exceed the given limits.
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        if let Some(ref mut decoder) = self.decoder {
+            decoder.set_limits(limits);
+        }
+
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Number of bits read, but not yet decoded, including the `0` bytes only counted.
+    fn available(&self) -> usize {
+        (self.buf.len() + self.zeros) * 8 - self.pos
+    }
+
+    /// Drop the bytes already decoded.
+    fn consume(&mut self) {
+        let consumed = self.pos / 8;
+        if consumed > self.buf.len() {
+            self.zeros -= consumed - self.buf.len();
+            self.buf.clear();
+        } else {
+            self.buf.drain(..consumed);
+        }
+
+        self.pos -= consumed * 8;
+        self.end = self.end.saturating_sub(consumed * 8);
+        self.base += consumed * 8;
+    }
+
+    /// Read the next chunk of bytes, returning `false` at the end of the input.
+    ///
+    /// Trailing `0` bytes are only counted, until followed by a set bit.
+    fn read_more(&mut self) -> io::Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+
+        self.consume();
+
+        // the counted zeros are stored once followed by a set bit, there are only few of them left
+        // after consuming blocks of 0s
+        let len = self.buf.len() + self.zeros;
+        self.buf.resize(len + BUFFER_SIZE, 0);
+        let read = loop {
+            match self.inner.read(&mut self.buf[len..]) {
+                Ok(read) => break read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buf.truncate(len - self.zeros);
+                    return Err(err);
+                }
+            }
+        };
+
+        match self.buf[len..len + read]
+            .iter()
+            .rposition(|&byte| byte != 0)
+        {
+            Some(i) => {
+                self.buf.truncate(len + i + 1);
+                self.end = self.buf.len() * 8 - self.buf[len + i].leading_zeros() as usize;
+                self.zeros = read - i - 1;
+            }
+            None => {
+                self.buf.truncate(len - self.zeros);
+                self.zeros += read;
+            }
+        }
+
+        self.eof = read == 0;
+        Ok(!self.eof)
+    }
+
+    fn read_run(&mut self) -> io::Result<Option<(bool, u64)>> {
+        // Header
+        if self.decoder.is_none() {
+            while self.buf.is_empty() && self.zeros == 0 {
+                if !self.read_more()? {
+                    // an empty encoding has no header
+                    return Ok(None);
+                }
+            }
+
+            let header = self.buf.first().map_or(false, |&byte| byte & 1 == 1);
+            let mut decoder = BlockDecoder::new(header, self.canonical);
+            decoder.set_limits(self.limits);
+            self.decoder = Some(decoder);
+            self.pos = 1;
+        }
+
+        loop {
+            // the blocks passed over are followed by a set bit, so they are runs of length 0
+            if self.skipped > 0 && self.pos < self.end {
+                let mut reader = BitReader::with_base(&[0, 0], 10, self.skipped_at);
+                let run = self.decoder.as_mut().unwrap().read_run(&mut reader)?;
+                self.skipped -= 1;
+                self.skipped_at += 10;
+
+                return Ok(run);
+            }
+
+            // only zeros are left, which are padding, unless a set bit follows
+            if self.pos >= self.end {
+                let offset = self.base + self.pos;
+
+                // 10 0s are a long block of length 0, or too much padding
+                let blocks = self.available() / 10;
+                if blocks > 0 {
+                    if self.canonical {
+                        return Err(RlePlusError::NonCanonical { offset }.into());
+                    }
+
+                    if self.skipped == 0 {
+                        self.skipped_at = offset;
+                    }
+                    self.skipped += blocks;
+                    self.pos += blocks * 10;
+                }
+
+                if !self.read_more()? {
+                    // the padding must end with the first byte that is not fully used
+                    if self.canonical && self.available() >= 8 {
+                        return Err(RlePlusError::NonCanonical { offset }.into());
+                    }
+
+                    self.decoder.as_ref().unwrap().finish()?;
+                    return Ok(None);
+                }
+
+                continue;
+            }
+
+            // a block ending in 0s can only take up to 2 of the counted bytes
+            let stored = self.zeros.min(2);
+            self.buf.resize(self.buf.len() + stored, 0);
+            self.zeros -= stored;
+
+            let mut reader = BitReader::with_base(&self.buf, self.buf.len() * 8, self.base);
+            reader.seek(self.base + self.pos);
+
+            match self.decoder.as_mut().unwrap().read_run(&mut reader) {
+                Ok(run) => {
+                    self.pos = reader.position() - self.base;
+                    return Ok(run);
+                }
+                // the block might continue in the next bytes
+                Err(RlePlusError::Truncated { .. }) | Err(RlePlusError::TrailingBits { .. })
+                    if !self.eof =>
+                {
+                    self.read_more()?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for RlePlusReader<R> {
+    type Item = io::Result<(bool, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_run() {
+            Ok(Some(run)) => Some(Ok(run)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::tests::random_bitvec;
+    use crate::rleplus::{encode_to_bytes, RunIter};
+
+    /// Hands out the bytes one at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_writer_roundtrip() {
+        let mut rng = XorShiftRng::from_seed([11u8; 16]);
+
+        for _i in 0..100 {
+            let original = random_bitvec(&mut rng, 10000);
+
+            let mut writer = RlePlusWriter::new(Vec::new());
+            for bit in original.iter() {
+                writer.push_bit(bit).unwrap();
+            }
+            assert_eq!(writer.position(), original.len() as u64);
+
+            assert_eq!(writer.finish().unwrap(), encode_to_bytes(&original));
+        }
+    }
+
+    #[test]
+    fn test_writer_indices() {
+        let mut writer = RlePlusWriter::new(Vec::new());
+        writer.push_index(2).unwrap();
+        writer.push_index(3).unwrap();
+        writer.push_index(1_000_000_000_000).unwrap();
+        assert!(writer.push_index(3).is_err());
+
+        let mut expected = RlePlusWriter::new(Vec::new());
+        expected.push_run(false, 2).unwrap();
+        expected.push_run(true, 2).unwrap();
+        expected.push_run(false, 1_000_000_000_000 - 4).unwrap();
+        expected.push_run(true, 1).unwrap();
+
+        assert_eq!(writer.finish().unwrap(), expected.finish().unwrap());
+    }
+
+    #[test]
+    fn test_reader_roundtrip() {
+        let mut rng = XorShiftRng::from_seed([12u8; 16]);
+
+        for _i in 0..200 {
+            let original = random_bitvec(&mut rng, 2000);
+            let mut bytes = encode_to_bytes(&original);
+
+            let expected = RunIter::from_bytes(&bytes).collect::<Result<Vec<_>, _>>();
+            let runs = RlePlusReader::canonical(&bytes[..]).collect::<io::Result<Vec<_>>>();
+            assert_eq!(runs.unwrap(), expected.clone().unwrap());
+            let runs = RlePlusReader::canonical(Trickle(&bytes)).collect::<io::Result<Vec<_>>>();
+            assert_eq!(runs.unwrap(), expected.clone().unwrap());
+
+            // lenient decoding accepts more padding
+            bytes.extend_from_slice(&[0, 0]);
+            let runs = RlePlusReader::new(Trickle(&bytes)).collect::<io::Result<Vec<_>>>();
+            assert_eq!(runs.unwrap(), expected.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reader_zero_tail() {
+        let zeros = 16 << 20;
+        let input = |last: &'static [u8]| {
+            (&[0b0100_0100u8][..])
+                .chain(io::repeat(0).take(zeros))
+                .chain(last)
+        };
+        let limits = DecodeLimits {
+            max_runs: 10,
+            ..Default::default()
+        };
+
+        // too much padding, or a long block of length 0
+        let err = RlePlusReader::canonical(input(&[]))
+            .with_limits(limits)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap_err();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<RlePlusError>(),
+            Some(&RlePlusError::NonCanonical { offset: 7 })
+        );
+
+        // the zeros are only counted
+        let mut reader = RlePlusReader::new(input(&[]));
+        let runs = reader.by_ref().collect::<io::Result<Vec<_>>>();
+        assert_eq!(runs.unwrap(), vec![(false, 8)]);
+        assert!(reader.buf.capacity() <= 4 * BUFFER_SIZE);
+
+        // followed by a set bit, the zeros are runs of length 0
+        let mut bytes = vec![0b0100_0100u8];
+        bytes.extend_from_slice(&[0; 100]);
+        bytes.push(1);
+        let expected = RunIter::from_bytes(&bytes)
+            .with_limits(limits)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        let err = RlePlusReader::new(input(&[1]))
+            .with_limits(limits)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap_err();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<RlePlusError>(),
+            Some(&expected)
+        );
+
+        let expected = RunIter::from_bytes(&bytes).collect::<Result<Vec<_>, _>>();
+        let runs = RlePlusReader::new(Trickle(&bytes)).collect::<io::Result<Vec<_>>>();
+        assert_eq!(runs.unwrap(), expected.unwrap());
+    }
+
+    #[test]
+    fn test_reader_errors() {
+        let cases: Vec<(&[u8], bool)> = vec![
+            // a whole byte of padding
+            (&[0b0100_0100, 0], true),
+            // a varint of more than 10 bytes
+            (
+                &[
+                    0b1111_1001,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                    0xff,
+                ],
+                false,
+            ),
+            // an unterminated varint
+            (&[0b1111_1001, 0xff], false),
+            // just a header
+            (&[0b0000_0001], true),
+        ];
+
+        for (i, (bytes, canonical)) in cases.into_iter().enumerate() {
+            let expected = if canonical {
+                RunIter::from_bytes_canonical(bytes).collect::<Result<Vec<_>, _>>()
+            } else {
+                RunIter::from_bytes(bytes).collect::<Result<Vec<_>, _>>()
+            }
+            .unwrap_err();
+
+            let reader = if canonical {
+                RlePlusReader::canonical(Trickle(bytes))
+            } else {
+                RlePlusReader::new(Trickle(bytes))
+            };
+            let err = reader.collect::<io::Result<Vec<_>>>().unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "case: {}", i);
+            assert_eq!(
+                err.get_ref().unwrap().downcast_ref::<RlePlusError>(),
+                Some(&expected),
+                "case: {}",
+                i
+            );
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Formatting and parsing [`BitField`]s in the [text format](index.html#text-format).
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::writer::RunEncoder;
+use super::BitField;
+
+/// Errors that can occur when parsing a [`BitField`] from text.
+///
+/// All positions are byte offsets into the text, pointing at the start of the offending index or range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseBitFieldError {
+    /// Expected an index, made up of decimal digits only.
+    InvalidIndex { position: usize },
+    /// The index does not fit into a `u64`, or the range ends at the largest one.
+    Overflow { position: usize },
+    /// The range ends before it starts.
+    ReversedRange { position: usize },
+    /// The range starts before the end of the previous one.
+    Unsorted { position: usize },
+    /// The length is smaller than the end of the last range.
+    InvalidLength { position: usize },
+}
+
+impl fmt::Display for ParseBitFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseBitFieldError::InvalidIndex { position } => {
+                write!(f, "invalid index at position {}", position)
+            }
+            ParseBitFieldError::Overflow { position } => {
+                write!(f, "index too large at position {}", position)
+            }
+            ParseBitFieldError::ReversedRange { position } => {
+                write!(f, "range ends before it starts at position {}", position)
+            }
+            ParseBitFieldError::Unsorted { position } => write!(
+                f,
+                "range not sorted, or overlapping the previous one at position {}",
+                position
+            ),
+            ParseBitFieldError::InvalidLength { position } => {
+                write!(f, "length shorter than the ranges at position {}", position)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseBitFieldError {}
+
+impl fmt::Display for BitField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut end = 0;
+        for (i, range) in self.ranges().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            if range.end - range.start == 1 {
+                write!(f, "{}", range.start)?;
+            } else {
+                write!(f, "{}-{}", range.start, range.end - 1)?;
+            }
+            end = range.end;
+        }
+
+        let len = self.len();
+        if len > end {
+            write!(f, "/{}", len)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for BitField {
+    type Err = ParseBitFieldError;
+
+    /// Parse a bitfield in the [text format](index.html#text-format).
+    ///
+    /// ```
+    /// use bitsets::rleplus::BitField;
+    ///
+    /// let bf: BitField = "1,3-7/10".parse().unwrap();
+    /// assert_eq!(bf.len(), 10);
+    /// assert_eq!(bf.count(), 6);
+    /// assert_eq!(bf.to_string(), "1,3-7/10");
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ranges, len) = match s.find('/') {
+            Some(i) => (&s[..i], Some(i + 1)),
+            None => (s, None),
+        };
+
+        let mut encoder = RunEncoder::new();
+        let mut end = 0;
+
+        let mut position = 0;
+        for range in ranges.split(',') {
+            let start = position;
+            position += range.len() + 1;
+
+            // no ranges at all
+            if range.is_empty() && ranges.is_empty() {
+                break;
+            }
+
+            let (first, last) = match range.find('-') {
+                Some(i) => (
+                    parse_index(&range[..i], start)?,
+                    parse_index(&range[i + 1..], start + i + 1)?,
+                ),
+                None => {
+                    let index = parse_index(range, start)?;
+                    (index, index)
+                }
+            };
+
+            if last < first {
+                return Err(ParseBitFieldError::ReversedRange { position: start });
+            }
+            if last == u64::max_value() {
+                return Err(ParseBitFieldError::Overflow { position: start });
+            }
+            if first < end {
+                return Err(ParseBitFieldError::Unsorted { position: start });
+            }
+
+            encoder.push_run(false, first - end);
+            encoder.push_run(true, last + 1 - first);
+            end = last + 1;
+        }
+
+        if let Some(position) = len {
+            let len = parse_index(&s[position..], position)?;
+            if len < end {
+                return Err(ParseBitFieldError::InvalidLength { position });
+            }
+
+            encoder.push_run(false, len - end);
+        }
+
+        Ok(BitField::from_canonical(encoder.finish()))
+    }
+}
+
+fn parse_index(s: &str, position: usize) -> Result<u64, ParseBitFieldError> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseBitFieldError::InvalidIndex { position });
+    }
+
+    s.parse()
+        .map_err(|_| ParseBitFieldError::Overflow { position })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitvec::*;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::encode;
+    use crate::rleplus::tests::random_bitvec;
+
+    #[test]
+    fn test_text_format() {
+        let cases: Vec<(BitVec<LittleEndian, u8>, &str)> = vec![
+            (BitVec::new(), ""),
+            (bitvec![LittleEndian; 0, 1], "1"),
+            (bitvec![LittleEndian; 0, 1, 0, 1, 1, 1, 1, 1], "1,3-7"),
+            (
+                bitvec![LittleEndian; 0, 1, 0, 1, 1, 1, 1, 1, 0, 0],
+                "1,3-7/10",
+            ),
+            (bitvec![LittleEndian; 0; 8], "/8"),
+        ];
+
+        for (i, (bits, text)) in cases.into_iter().enumerate() {
+            let bf = BitField::from_bits(&bits);
+            assert_eq!(bf.to_string(), text, "case: {}", i);
+            assert_eq!(text.parse(), Ok(bf), "case: {}", i);
+        }
+
+        // ranges may touch, and end with the length
+        let bf: BitField = "0-2,3,4-5/6".parse().unwrap();
+        assert_eq!(bf.to_string(), "0-5");
+    }
+
+    #[test]
+    fn test_text_errors() {
+        let cases = vec![
+            ("a", ParseBitFieldError::InvalidIndex { position: 0 }),
+            ("1,", ParseBitFieldError::InvalidIndex { position: 2 }),
+            (",1", ParseBitFieldError::InvalidIndex { position: 0 }),
+            ("1, 2", ParseBitFieldError::InvalidIndex { position: 2 }),
+            ("1,3-", ParseBitFieldError::InvalidIndex { position: 4 }),
+            ("1,-3", ParseBitFieldError::InvalidIndex { position: 2 }),
+            ("+1", ParseBitFieldError::InvalidIndex { position: 0 }),
+            ("1-2-3", ParseBitFieldError::InvalidIndex { position: 2 }),
+            ("1/", ParseBitFieldError::InvalidIndex { position: 2 }),
+            ("1/2/3", ParseBitFieldError::InvalidIndex { position: 2 }),
+            (
+                "1,18446744073709551616",
+                ParseBitFieldError::Overflow { position: 2 },
+            ),
+            (
+                "0-18446744073709551615",
+                ParseBitFieldError::Overflow { position: 0 },
+            ),
+            ("1,7-3", ParseBitFieldError::ReversedRange { position: 2 }),
+            ("1,3-7,5", ParseBitFieldError::Unsorted { position: 6 }),
+            ("3,1", ParseBitFieldError::Unsorted { position: 2 }),
+            ("1,3-7/7", ParseBitFieldError::InvalidLength { position: 6 }),
+        ];
+
+        for (text, err) in cases {
+            assert_eq!(text.parse::<BitField>(), Err(err), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let mut rng = XorShiftRng::from_seed([23u8; 16]);
+
+        for _i in 0..1000 {
+            let bits = random_bitvec(&mut rng, 30);
+            let text = BitField::from_bits(&bits).to_string();
+
+            let parsed: BitField = text.parse().unwrap();
+            assert_eq!(parsed.encoding(), &encode(&bits), "{}", text);
+        }
+    }
+}
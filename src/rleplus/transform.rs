@@ -0,0 +1,616 @@
+//! Operations building a new encoding from parts of RLE+ encoded bitsets, computed on their runs.
+
+use std::ops::Range;
+
+use bitvec::*;
+
+use super::iter::RunIter;
+use super::ops::RunCursor;
+use super::reader::BitReader;
+use super::writer::RunEncoder;
+use super::RlePlusError;
+
+/// The bits of an RLE+ encoded bitset within `range`, moved to start at `0`, returned in their
+/// canonical encoding.
+///
+/// The slice ends with the bitset, if that ends before `range`.
+///
+/// ```
+/// use bitsets::rleplus::{decode, encode, slice};
+/// use bitvec::*;
+///
+/// let enc = encode(&bitvec![LittleEndian; 0, 0, 1, 1, 1, 0, 1]);
+/// assert_eq!(decode(&slice(&enc, 3..6).unwrap()), bitvec![LittleEndian; 1, 1, 0]);
+/// ```
+pub fn slice(
+    enc: &BitVec<LittleEndian, u8>,
+    range: Range<u64>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    slice_inner(enc, range, true)
+}
+
+/// The bits of an RLE+ encoded bitset within `range`, kept at their positions, returned in their
+/// canonical encoding.
+///
+/// All bits before `range` are unset, and the result ends with `range`, or the bitset if that ends before.
+pub fn slice_absolute(
+    enc: &BitVec<LittleEndian, u8>,
+    range: Range<u64>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    slice_inner(enc, range, false)
+}
+
+fn slice_inner(
+    enc: &BitVec<LittleEndian, u8>,
+    range: Range<u64>,
+    rebase: bool,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let start = range.start;
+    let end = range.end.max(start);
+
+    let mut encoder = RunEncoder::new();
+    let mut pos = 0;
+
+    for run in RunIter::new(enc) {
+        let (value, len) = run?;
+        let run_start = pos;
+        pos += len;
+
+        if run_start >= end {
+            break;
+        }
+
+        // the part of the run before the range
+        if !rebase && run_start < start {
+            encoder.push_run(false, pos.min(start) - run_start);
+        }
+
+        if pos > start {
+            encoder.push_run(value, pos.min(end) - run_start.max(start));
+        }
+    }
+
+    Ok(encoder.finish())
+}
+
+/// Move all bits of an RLE+ encoded bitset `n` positions to the front, dropping the first `n` bits,
+/// returned in their canonical encoding.
+pub fn shift_left(
+    enc: &BitVec<LittleEndian, u8>,
+    n: u64,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let mut runs = RunIter::new(enc);
+    let mut pos = 0;
+
+    while let Some(run) = runs.next() {
+        let (value, len) = run?;
+        pos += len;
+
+        // the run holding the new first bit
+        if pos > n {
+            let mut encoder = RunEncoder::new();
+            encoder.push_run(value, pos - n);
+
+            return finish_with_tail(encoder, runs, enc, pos - n);
+        }
+    }
+
+    Ok(BitVec::new())
+}
+
+/// Move all bits of an RLE+ encoded bitset `n` positions to the back, inserting `n` unset bits at the front,
+/// returned in their canonical encoding.
+///
+/// Fails with [`RlePlusError::LengthOverflow`] if the result is longer than `u64::max_value()` bits.
+///
+/// ```
+/// use bitsets::rleplus::{decode, encode, shift_left, shift_right};
+/// use bitvec::*;
+///
+/// let enc = encode(&bitvec![LittleEndian; 1, 1, 0, 1]);
+/// assert_eq!(decode(&shift_right(&enc, 2).unwrap()), bitvec![LittleEndian; 0, 0, 1, 1, 0, 1]);
+/// assert_eq!(decode(&shift_left(&enc, 1).unwrap()), bitvec![LittleEndian; 1, 0, 1]);
+/// ```
+pub fn shift_right(
+    enc: &BitVec<LittleEndian, u8>,
+    n: u64,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let mut encoder = RunEncoder::new();
+    encoder.push_run(false, n);
+
+    push_rest(encoder, RunIter::new(enc), enc, n)
+}
+
+/// Join two RLE+ encoded bitsets, with `b` starting at position `offset`, and the bits between the end
+/// of `a` and `offset` unset, returned in their canonical encoding.
+///
+/// Fails with [`RlePlusError::Overlap`] if `offset` is smaller than the length of `a`, and with
+/// [`RlePlusError::LengthOverflow`] if the result is longer than `u64::max_value()` bits.
+pub fn concat(
+    a: &BitVec<LittleEndian, u8>,
+    b: &BitVec<LittleEndian, u8>,
+    offset: u64,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let mut encoder = RunEncoder::new();
+    let mut runs = RunIter::new(a);
+    let mut pos = 0;
+
+    loop {
+        let block = runs.position();
+        let (value, len) = match runs.next() {
+            Some(run) => run?,
+            None => break,
+        };
+
+        pos += len;
+        if pos > offset {
+            return Err(RlePlusError::Overlap { offset: block });
+        }
+        encoder.push_run(value, len);
+    }
+
+    encoder.push_run(false, offset - pos);
+
+    push_rest(encoder, RunIter::new(b), b, offset)
+}
+
+/// Remove all positions set in `m` from the RLE+ encoded bitset `b`, moving the remaining bits down to
+/// close the gaps, returned in its canonical encoding.
+///
+/// The result is as long as the number of positions of `b` not set in `m`.
+///
+/// ```
+/// use bitsets::rleplus::{cut, decode, encode};
+/// use bitvec::*;
+///
+/// let b = encode(&bitvec![LittleEndian; 1, 0, 1, 1, 0, 1]);
+/// let m = encode(&bitvec![LittleEndian; 0, 1, 1, 0, 0, 0]);
+/// assert_eq!(decode(&cut(&b, &m).unwrap()), bitvec![LittleEndian; 1, 1, 0, 1]);
+/// ```
+pub fn cut(
+    b: &BitVec<LittleEndian, u8>,
+    m: &BitVec<LittleEndian, u8>,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let mut b = RunCursor::new(RunIter::new(b));
+    let mut m = RunCursor::new(RunIter::new(m));
+    let mut encoder = RunEncoder::new();
+
+    while let Some((value, len)) = b.current()? {
+        let (len, removed) = match m.current()? {
+            Some((removed, m_len)) => (len.min(m_len), removed),
+            // the mask is extended with 0s
+            None => (len, false),
+        };
+
+        if !removed {
+            encoder.push_run(value, len);
+        }
+        b.advance(len);
+        m.advance(len);
+    }
+
+    Ok(encoder.finish())
+}
+
+/// The first `n` set bits of an RLE+ encoded bitset, kept at their positions, returned in their canonical
+/// encoding.
+///
+/// The result ends with the last bit taken. If fewer than `n` bits are set, all of them are taken.
+pub fn take_first(
+    enc: &BitVec<LittleEndian, u8>,
+    n: u64,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let mut first = RunEncoder::new();
+    take_ones(&mut RunIter::new(enc), n, &mut first)?;
+
+    Ok(first.finish())
+}
+
+/// Split an RLE+ encoded bitset into the first `n` set bits, as returned by [`take_first`], and the remaining
+/// ones, both kept at their positions, and returned in their canonical encoding.
+///
+/// The remainder is as long as the bitset, so that the union of both halves is the bitset again.
+///
+/// ```
+/// use bitsets::rleplus::{decode, encode, split_at_count};
+/// use bitvec::*;
+///
+/// let enc = encode(&bitvec![LittleEndian; 0, 1, 1, 1, 0, 1]);
+/// let (first, rest) = split_at_count(&enc, 2).unwrap();
+/// assert_eq!(decode(&first), bitvec![LittleEndian; 0, 1, 1]);
+/// assert_eq!(decode(&rest), bitvec![LittleEndian; 0, 0, 0, 1, 0, 1]);
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn split_at_count(
+    enc: &BitVec<LittleEndian, u8>,
+    n: u64,
+) -> Result<(BitVec<LittleEndian, u8>, BitVec<LittleEndian, u8>), RlePlusError> {
+    let mut runs = RunIter::new(enc);
+    let mut first = RunEncoder::new();
+    let (pos, split) = take_ones(&mut runs, n, &mut first)?;
+
+    // all bits taken are unset in the remainder
+    let mut rest = RunEncoder::new();
+    rest.push_run(false, pos);
+
+    let rest = match split {
+        Some(len) => {
+            rest.push_run(true, len);
+            finish_with_tail(rest, runs, enc, pos + len)?
+        }
+        None => push_rest(rest, runs, enc, pos)?,
+    };
+
+    Ok((first.finish(), rest))
+}
+
+/// Push runs into `first` until it holds `n` set bits, leaving out any unset bits after the last one.
+///
+/// Returns the number of bits consumed, and the number of set bits left in the last run, if it was split.
+fn take_ones(
+    runs: &mut RunIter,
+    n: u64,
+    first: &mut RunEncoder,
+) -> Result<(u64, Option<u64>), RlePlusError> {
+    let mut pos = 0;
+    let mut ones = 0;
+    // unset bits, only pushed once followed by set ones
+    let mut zeros = 0;
+
+    while ones < n {
+        let (value, len) = match runs.next() {
+            Some(run) => run?,
+            None => break,
+        };
+
+        if !value {
+            zeros += len;
+            pos += len;
+            continue;
+        }
+
+        let take = len.min(n - ones);
+        first.push_run(false, zeros);
+        first.push_run(true, take);
+        zeros = 0;
+        ones += take;
+        pos += take;
+
+        if take < len {
+            return Ok((pos, Some(len - take)));
+        }
+    }
+
+    Ok((pos, None))
+}
+
+/// Push the remaining runs up to the first non-empty one, and copy the blocks after it.
+///
+/// As the blocks after it continue with the opposite value, they are not affected by what was pushed before.
+/// `len` is the number of bits pushed before.
+fn push_rest(
+    mut encoder: RunEncoder,
+    mut runs: RunIter,
+    enc: &BitVec<LittleEndian, u8>,
+    mut len: u64,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    loop {
+        let offset = runs.position();
+        let (value, run) = match runs.next() {
+            Some(run) => run?,
+            None => return Ok(encoder.finish()),
+        };
+
+        len = len
+            .checked_add(run)
+            .ok_or(RlePlusError::LengthOverflow { offset })?;
+        encoder.push_run(value, run);
+
+        if run > 0 {
+            return finish_with_tail(encoder, runs, enc, len);
+        }
+    }
+}
+
+/// Write the pending run, followed by the remaining blocks of `runs`, copied from `enc` once they are
+/// validated.
+///
+/// Blocks which are not in their canonical form are encoded again instead. `len` is the number of bits
+/// pushed before, which the copied blocks must not make overflow.
+fn finish_with_tail(
+    mut encoder: RunEncoder,
+    mut runs: RunIter,
+    enc: &BitVec<LittleEndian, u8>,
+    mut len: u64,
+) -> Result<BitVec<LittleEndian, u8>, RlePlusError> {
+    let tail = runs.clone();
+    let start = runs.position();
+    let mut canonical = true;
+
+    loop {
+        let offset = runs.position();
+        let (_, run) = match runs.next() {
+            Some(run) => run?,
+            None => break,
+        };
+
+        len = len
+            .checked_add(run)
+            .ok_or(RlePlusError::LengthOverflow { offset })?;
+        canonical = canonical && run > 0 && runs.position() - offset == RunEncoder::block_size(run);
+    }
+
+    if !canonical {
+        for run in tail {
+            let (value, run) = run?;
+            encoder.push_run(value, run);
+        }
+
+        return Ok(encoder.finish());
+    }
+
+    let mut writer = encoder.finish_writer();
+
+    let mut reader = BitReader::new(enc.as_ref(), enc.len());
+    reader.seek(start);
+    while reader.remaining() > 0 {
+        let n = reader.remaining().min(8);
+        writer.write_bits(reader.read_bits(n).unwrap(), n);
+    }
+
+    Ok(writer.into_bitvec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::rleplus::tests::random_bitvec;
+    use crate::rleplus::{decode, encode, is_canonical};
+
+    #[test]
+    fn test_shift() {
+        let mut rng = XorShiftRng::from_seed([17u8; 16]);
+
+        for _i in 0..1000 {
+            let bits = random_bitvec(&mut rng, 50);
+            let enc = encode(&bits);
+            let n = rng.gen_range(0, bits.len() as u64 + 20);
+
+            let expected: BitVec<LittleEndian, u8> = bits.iter().skip(n as usize).collect();
+            assert_eq!(shift_left(&enc, n), Ok(encode(&expected)));
+
+            let expected: BitVec<LittleEndian, u8> =
+                (0..n).map(|_| false).chain(bits.iter()).collect();
+            assert_eq!(shift_right(&enc, n), Ok(encode(&expected)));
+        }
+
+        // an empty first run, followed by 3 unset bits
+        let enc = bitvec![LittleEndian; 1, 0, 1, 0, 0, 0, 0, 0, 1, 1, 1, 0, 0];
+        assert_eq!(
+            shift_right(&enc, 2),
+            Ok(encode(&bitvec![LittleEndian; 0; 5]))
+        );
+
+        // the result can be u64::max_value() bits long, but not longer
+        let enc = encode(&bitvec![LittleEndian; 0, 0, 1]);
+        let mut expected = RunEncoder::new();
+        expected.push_run(false, u64::max_value() - 1);
+        expected.push_run(true, 1);
+        assert_eq!(
+            shift_right(&enc, u64::max_value() - 3),
+            Ok(expected.finish())
+        );
+        assert_eq!(
+            shift_right(&enc, u64::max_value() - 2),
+            Err(RlePlusError::LengthOverflow { offset: 7 })
+        );
+        assert_eq!(
+            shift_right(&enc, u64::max_value() - 1),
+            Err(RlePlusError::LengthOverflow { offset: 1 })
+        );
+
+        // blocks which are not canonical are encoded again
+        let enc = bitvec![LittleEndian;
+            1, // starts with 1
+            1, // 1
+            0, 1, 0, 0, 1, 0, // 4
+            1, // 1
+            0, 0, 1, 1, 0, 0, 0, 0, 0, 0, // 3, as a long block
+            1, // 1
+        ];
+        let bits = decode(&enc);
+        assert!(!is_canonical(&enc));
+        for n in 0..4 {
+            let expected: BitVec<LittleEndian, u8> = bits.iter().skip(n as usize).collect();
+            assert_eq!(shift_left(&enc, n), Ok(encode(&expected)));
+            let expected: BitVec<LittleEndian, u8> =
+                (0..n).map(|_| false).chain(bits.iter()).collect();
+            assert_eq!(shift_right(&enc, n), Ok(encode(&expected)));
+            let expected: BitVec<LittleEndian, u8> = bits
+                .iter()
+                .chain((0..n).map(|_| false))
+                .chain(bits.iter())
+                .collect();
+            assert_eq!(
+                concat(&enc, &enc, bits.len() as u64 + n),
+                Ok(encode(&expected))
+            );
+        }
+
+        // the blocks after the first run are validated
+        let enc = bitvec![LittleEndian; 0, 1, 0];
+        assert_eq!(
+            shift_right(&enc, 3),
+            Err(RlePlusError::TrailingBits { offset: 2 })
+        );
+        assert_eq!(
+            shift_left(&enc, 0),
+            Err(RlePlusError::TrailingBits { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn test_concat() {
+        let mut rng = XorShiftRng::from_seed([18u8; 16]);
+
+        for _i in 0..1000 {
+            let a = random_bitvec(&mut rng, 50);
+            let b = random_bitvec(&mut rng, 50);
+            let offset = a.len() as u64 + rng.gen_range(0, 20);
+
+            let expected: BitVec<LittleEndian, u8> = a
+                .iter()
+                .chain((a.len() as u64..offset).map(|_| false))
+                .chain(b.iter())
+                .collect();
+            assert_eq!(
+                concat(&encode(&a), &encode(&b), offset),
+                Ok(encode(&expected))
+            );
+        }
+
+        let a = encode(&bitvec![LittleEndian; 1]);
+        let b = encode(&bitvec![LittleEndian; 1, 0]);
+        assert_eq!(
+            concat(&a, &b, u64::max_value() - 1),
+            Err(RlePlusError::LengthOverflow { offset: 2 })
+        );
+        assert_eq!(
+            concat(&a, &bitvec![LittleEndian; 1, 1, 0], 1),
+            Err(RlePlusError::TrailingBits { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn test_concat_overlap() {
+        let a = encode(&bitvec![LittleEndian; 1, 1, 0, 1]);
+        assert_eq!(concat(&a, &a, 3), Err(RlePlusError::Overlap { offset: 8 }));
+        assert_eq!(
+            concat(&a, &a, 4),
+            Ok(encode(&bitvec![LittleEndian; 1, 1, 0, 1, 1, 1, 0, 1]))
+        );
+    }
+
+    #[test]
+    fn test_cut() {
+        let mut rng = XorShiftRng::from_seed([19u8; 16]);
+
+        for _i in 0..1000 {
+            let b = random_bitvec(&mut rng, 50);
+            let m = random_bitvec(&mut rng, 50);
+
+            let expected: BitVec<LittleEndian, u8> = b
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| !m.get(i).unwrap_or(false))
+                .map(|(_, bit)| bit)
+                .collect();
+            assert_eq!(cut(&encode(&b), &encode(&m)), Ok(encode(&expected)));
+        }
+
+        // cutting everything
+        let b = encode(&bitvec![LittleEndian; 1, 0, 1]);
+        let m = encode(&bitvec![LittleEndian; 1, 1, 1, 1]);
+        assert!(cut(&b, &m).unwrap().is_empty());
+
+        let m = bitvec![LittleEndian; 0, 1, 0];
+        assert_eq!(cut(&b, &m), Err(RlePlusError::TrailingBits { offset: 2 }));
+    }
+
+    #[test]
+    fn test_take_first() {
+        let mut rng = XorShiftRng::from_seed([20u8; 16]);
+
+        for _i in 0..1000 {
+            let bits = random_bitvec(&mut rng, 50);
+            let enc = encode(&bits);
+
+            let ones: Vec<usize> = bits
+                .iter()
+                .enumerate()
+                .filter(|&(_, bit)| bit)
+                .map(|(i, _)| i)
+                .collect();
+            let n = rng.gen_range(0, ones.len() + 4);
+            let taken = &ones[..n.min(ones.len())];
+
+            let end = taken.last().map_or(0, |&i| i + 1);
+            let expected_first: BitVec<LittleEndian, u8> =
+                (0..end).map(|i| taken.contains(&i)).collect();
+            let expected_rest: BitVec<LittleEndian, u8> = bits
+                .iter()
+                .enumerate()
+                .map(|(i, bit)| bit && !taken.contains(&i))
+                .collect();
+
+            assert_eq!(take_first(&enc, n as u64), Ok(encode(&expected_first)));
+            assert_eq!(
+                split_at_count(&enc, n as u64),
+                Ok((encode(&expected_first), encode(&expected_rest)))
+            );
+        }
+
+        // blocks after the split which are not canonical are encoded again
+        let enc = bitvec![LittleEndian; 1, 1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1];
+        let (first, rest) = split_at_count(&enc, 1).unwrap();
+        assert_eq!(first, encode(&bitvec![LittleEndian; 1]));
+        assert_eq!(
+            rest,
+            encode(&bitvec![LittleEndian; 0, 0, 0, 0, 0, 1, 0, 0, 0, 1])
+        );
+        assert!(is_canonical(&rest));
+
+        // the blocks after the split are validated
+        let enc = bitvec![LittleEndian; 1, 1, 1, 0];
+        assert_eq!(
+            split_at_count(&enc, 1),
+            Err(RlePlusError::TrailingBits { offset: 3 })
+        );
+        let enc = bitvec![LittleEndian; 1, 0, 1, 1, 1, 0, 0, 0];
+        assert_eq!(
+            split_at_count(&enc, 1),
+            Err(RlePlusError::TrailingBits { offset: 7 })
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let mut rng = XorShiftRng::from_seed([16u8; 16]);
+
+        for _i in 0..1000 {
+            let bits = random_bitvec(&mut rng, 50);
+            let enc = encode(&bits);
+
+            let len = bits.len() as u64;
+            let start = rng.gen_range(0, len + 16);
+            let end = rng.gen_range(start, len + 32);
+            let clipped = (start.min(len) as usize)..(end.min(len) as usize);
+
+            let expected: BitVec<LittleEndian, u8> = bits
+                .iter()
+                .skip(clipped.start)
+                .take(clipped.len())
+                .collect();
+            assert_eq!(slice(&enc, start..end), Ok(encode(&expected)));
+
+            let expected: BitVec<LittleEndian, u8> = bits
+                .iter()
+                .enumerate()
+                .take(clipped.end)
+                .map(|(i, bit)| bit && i >= clipped.start)
+                .collect();
+            assert_eq!(slice_absolute(&enc, start..end), Ok(encode(&expected)));
+        }
+
+        let enc = encode(&bitvec![LittleEndian; 1, 1, 0, 1]);
+        assert!(slice(&enc, 2..2).unwrap().is_empty());
+        assert_eq!(
+            decode(&slice_absolute(&enc, 1..3).unwrap()),
+            bitvec![LittleEndian; 0, 1, 0]
+        );
+    }
+}
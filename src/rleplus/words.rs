@@ -0,0 +1,74 @@
+use bitvec::*;
+
+/// Iterator over the runs of a raw bitset, scanning it 64 bits at a time.
+///
+/// Yields `(value, length)` pairs, with alternating values and lengths of at least `1`.
+#[derive(Debug, Clone)]
+pub(crate) struct WordRuns<'a> {
+    bytes: &'a [u8],
+    /// Number of bits in the bitset.
+    len: usize,
+    /// Start of the next run.
+    pos: usize,
+    /// Value of the next run.
+    cur: bool,
+}
+
+impl<'a> WordRuns<'a> {
+    pub fn new(raw: &'a BitVec<LittleEndian, u8>) -> Self {
+        WordRuns {
+            bytes: raw.as_ref(),
+            len: raw.len(),
+            pos: 0,
+            cur: raw.get(0).unwrap_or(false),
+        }
+    }
+
+    /// The 64 bits starting at bit `64 * i`, with bits past the end of the bytes unset.
+    fn word(&self, i: usize) -> u64 {
+        let start = i * 8;
+        let end = (start + 8).min(self.bytes.len());
+
+        let mut buf = [0u8; 8];
+        buf[..end - start].copy_from_slice(&self.bytes[start..end]);
+
+        u64::from_le_bytes(buf)
+    }
+}
+
+impl<'a> Iterator for WordRuns<'a> {
+    type Item = (bool, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.len {
+            return None;
+        }
+
+        let start = self.pos;
+        let mut end = start;
+
+        while end < self.len {
+            let shift = end % 64;
+            let mut word = self.word(end / 64) >> shift;
+            if self.cur {
+                // count trailing ones as trailing zeros
+                word = !word;
+            }
+
+            // bits beyond the word, or the bitset, are not part of the run
+            let avail = (64 - shift).min(self.len - end);
+            let same = (word.trailing_zeros() as usize).min(avail);
+            end += same;
+
+            if same < avail {
+                break;
+            }
+        }
+
+        let value = self.cur;
+        self.pos = end;
+        self.cur = !value;
+
+        Some((value, (end - start) as u64))
+    }
+}
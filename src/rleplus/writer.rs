@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+
+use bitvec::*;
+
+/// Appends bits, least significant bit first, to a byte vector.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BitWriter {
+    bytes: Vec<u8>,
+    /// Number of bits written.
+    len: usize,
+}
+
+impl BitWriter {
+    /// Preallocate space for `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        BitWriter {
+            bytes: Vec::with_capacity(capacity),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        self.write_bits(bit as u8, 1);
+    }
+
+    /// Write the lowest `n` bits of `bits`, with `n <= 8`, least significant bit first.
+    pub fn write_bits(&mut self, bits: u8, n: usize) {
+        debug_assert!(n <= 8);
+
+        let shift = self.len % 8;
+        let bits = u16::from(bits) & ((1 << n) - 1);
+
+        if shift == 0 {
+            self.bytes.push(bits as u8);
+        } else {
+            *self.bytes.last_mut().unwrap() |= (bits << shift) as u8;
+            if shift + n > 8 {
+                self.bytes.push((bits >> (8 - shift)) as u8);
+            }
+        }
+
+        self.len += n;
+    }
+
+    /// Number of bytes that are completely written.
+    pub fn complete_bytes(&self) -> usize {
+        self.len / 8
+    }
+
+    /// Write out the bytes that are completely written, and remove them.
+    pub fn drain_to<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let n = self.complete_bytes();
+
+        w.write_all(&self.bytes[..n])?;
+        self.bytes.drain(..n);
+        self.len -= n * 8;
+
+        Ok(())
+    }
+
+    /// The written bits, with the last byte padded with `0`s.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn into_bitvec(self) -> BitVec<LittleEndian, u8> {
+        let len = self.len;
+        let mut bits: BitVec<LittleEndian, u8> = self.bytes.into();
+        bits.truncate(len);
+
+        bits
+    }
+}
+
+/// Set the bits `start..start + len` of `buf`, stored least significant bit first, to `value`.
+///
+/// Whole bytes inside the range are written at once.
+pub(crate) fn fill_bits(buf: &mut [u8], start: usize, len: usize, value: bool) {
+    let end = start + len;
+    debug_assert!(end <= buf.len() * 8);
+
+    let mut pos = start;
+
+    // bits up to the next byte boundary
+    while pos < end && pos % 8 != 0 {
+        set_bit(buf, pos, value);
+        pos += 1;
+    }
+
+    let fill = if value { 0xff } else { 0x00 };
+    for byte in &mut buf[pos / 8..end / 8] {
+        *byte = fill;
+    }
+    pos = pos.max(end / 8 * 8);
+
+    // bits after the last byte boundary
+    while pos < end {
+        set_bit(buf, pos, value);
+        pos += 1;
+    }
+}
+
+fn set_bit(buf: &mut [u8], pos: usize, value: bool) {
+    if value {
+        buf[pos / 8] |= 1 << (pos % 8);
+    } else {
+        buf[pos / 8] &= !(1 << (pos % 8));
+    }
+}
+
+/// Writes runs of bits as RLE+ blocks, producing the canonical encoding.
+///
+/// Runs can be pushed in any size, consecutive runs of the same value are joined and empty runs are skipped.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RunEncoder {
+    writer: BitWriter,
+    /// The run not yet written, as it might be extended by the next one.
+    pending: Option<(bool, u64)>,
+}
+
+impl RunEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preallocate space for `capacity` bytes of encoding.
+    pub fn with_capacity(capacity: usize) -> Self {
+        RunEncoder {
+            writer: BitWriter::with_capacity(capacity),
+            pending: None,
+        }
+    }
+
+    pub fn push_run(&mut self, value: bool, len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        match self.pending {
+            Some((cur, ref mut cur_len)) if cur == value => *cur_len += len,
+            Some((_, cur_len)) => {
+                self.write_block(cur_len);
+                self.pending = Some((value, len));
+            }
+            None => {
+                // Header
+                self.writer.push(value);
+                self.pending = Some((value, len));
+            }
+        }
+    }
+
+    fn write_block(&mut self, len: u64) {
+        if len == 1 {
+            // Block Single
+            self.writer.push(true);
+        } else if len < 16 {
+            // Block Short
+            // prefix: 01
+            self.writer.push(false);
+            self.writer.push(true);
+            self.writer.write_bits(len as u8, 4);
+        } else {
+            // Block Long
+            // prefix: 00
+            self.writer.push(false);
+            self.writer.push(false);
+
+            let mut v = [0u8; 10];
+            for byte in unsigned_varint::encode::u64(len, &mut v) {
+                self.writer.write_bits(*byte, 8);
+            }
+        }
+    }
+
+    /// Number of bits of the block `write_block` writes for a run of `len`, with `len > 0`.
+    pub fn block_size(len: u64) -> usize {
+        if len == 1 {
+            1
+        } else if len < 16 {
+            6
+        } else {
+            let mut v = [0u8; 10];
+            2 + 8 * unsigned_varint::encode::u64(len, &mut v).len()
+        }
+    }
+
+    /// The writer holding the blocks written so far, not including the pending run.
+    pub fn writer_mut(&mut self) -> &mut BitWriter {
+        &mut self.writer
+    }
+
+    /// Write the pending run, and return the writer holding all blocks.
+    pub fn finish_writer(mut self) -> BitWriter {
+        if let Some((_, len)) = self.pending.take() {
+            self.write_block(len);
+        }
+
+        self.writer
+    }
+
+    pub fn finish(self) -> BitVec<LittleEndian, u8> {
+        self.finish_writer().into_bitvec()
+    }
+}